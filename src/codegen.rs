@@ -0,0 +1,365 @@
+//! Generate a typed `Config` struct from a charm's config.yaml at build time.
+//!
+//! Reading options with `config_get("brick_paths")` means a typo is only found once the charm
+//! is deployed.  Generating the struct from config.yaml turns those typos into compile errors.
+//!
+//! Add juju as a build dependency and call `write_config` from your build.rs:
+//!
+//! ```no_run
+//! extern crate juju;
+//! use std::env;
+//! use std::path::Path;
+//!
+//! fn main() {
+//!     let out_dir = env::var("OUT_DIR").unwrap();
+//!     juju::codegen::write_config("config.yaml", Path::new(&out_dir).join("config.rs"))
+//!         .unwrap();
+//!     println!("cargo:rerun-if-changed=config.yaml");
+//! }
+//! ```
+//!
+//! Then include the generated code in your charm and load it in a hook:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/config.rs"));
+//!
+//! fn config_changed() -> Result<(), String> {
+//!     let config = Config::load().map_err(|e| e.to_string())?;
+//!     for brick in config.brick_paths.split_whitespace() {
+//!         // ...
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_yaml::{self, Value};
+
+use JujuError;
+
+/// Rust keywords that can not be used as plain field names
+const KEYWORDS: &[&str] = &["as", "break", "const", "continue", "crate", "else", "enum",
+                            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+                            "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+                            "struct", "trait", "true", "type", "unsafe", "use", "where",
+                            "while", "async", "await", "dyn", "abstract", "become", "box",
+                            "do", "final", "macro", "override", "priv", "typeof", "unsized",
+                            "virtual", "yield", "try"];
+
+/// Keywords that can not be raw identifiers either, so get a trailing underscore instead
+const RESERVED: &[&str] = &["crate", "self", "super", "_"];
+
+/// A single option from config.yaml
+#[derive(Debug)]
+struct ConfigOption {
+    name: String,
+    field: String,
+    rust_type: &'static str,
+    default: Option<Value>,
+    description: Option<String>,
+}
+
+impl ConfigOption {
+    fn field_type(&self) -> String {
+        match self.default {
+            Some(_) => self.rust_type.to_string(),
+            None => format!("Option<{}>", self.rust_type),
+        }
+    }
+
+    /// The default value as a Rust expression
+    fn default_expr(&self) -> Result<String, JujuError> {
+        let default = match self.default {
+            Some(ref default) => default,
+            None => return Ok("None".to_string()),
+        };
+        let expr = match (self.rust_type, default) {
+            ("String", Value::String(s)) => format!("{:?}.to_string()", s),
+            // Juju will happily take `default: 3` for a string option
+            ("String", other) => format!("{:?}.to_string()", yaml_scalar(other)?),
+            ("bool", Value::Bool(b)) => b.to_string(),
+            ("i64", Value::Number(n)) if n.is_i64() => n.to_string(),
+            ("f64", Value::Number(n)) => {
+                format!("{:?}", n.as_f64().unwrap_or_default())
+            }
+            (rust_type, other) => {
+                let message = format!("Default for option {} is not a valid {}",
+                                      self.name,
+                                      rust_type);
+                return Err(JujuError::parse(&message, &format!("{:?}", other)));
+            }
+        };
+        Ok(expr)
+    }
+}
+
+fn yaml_scalar(value: &Value) -> Result<String, JujuError> {
+    match *value {
+        Value::String(ref s) => Ok(s.clone()),
+        Value::Number(ref n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        ref other => Err(JujuError::parse("Expected a scalar value", &format!("{:?}", other))),
+    }
+}
+
+/// Turn an option name like `brick-paths` or `type` into a usable field name.  Keywords
+/// become raw identifiers, except the few that can not be, which get a trailing underscore.
+fn field_name(option: &str) -> String {
+    let mut field: String = option.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if field.starts_with(|c: char| c.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    if RESERVED.contains(&field.as_ref()) {
+        field.push('_');
+    } else if KEYWORDS.contains(&field.as_ref()) {
+        field.insert_str(0, "r#");
+    }
+    field
+}
+
+fn parse_options(config_yaml: &str) -> Result<Vec<ConfigOption>, JujuError> {
+    let document: Value = serde_yaml::from_str(config_yaml)?;
+    let options = match document.get("options") {
+        Some(Value::Mapping(options)) => options,
+        Some(&Value::Null) | None => return Ok(Vec::new()),
+        Some(other) => {
+            return Err(JujuError::parse("Expected options to be a map", &format!("{:?}", other)))
+        }
+    };
+
+    let mut parsed: Vec<ConfigOption> = Vec::new();
+    for (name, option) in options {
+        let name = yaml_scalar(name)?;
+        let rust_type = match option.get("type").and_then(|t| t.as_str()).unwrap_or("string") {
+            "string" => "String",
+            "int" => "i64",
+            "float" => "f64",
+            "boolean" => "bool",
+            // The value is a secret URI the charm reads with secret_get
+            "secret" => "String",
+            other => {
+                return Err(JujuError::parse(&format!("Unknown type for option {}", name), other))
+            }
+        };
+        let default = match option.get("default") {
+            Some(&Value::Null) | None => None,
+            Some(default) => Some(default.clone()),
+        };
+        let field = field_name(&name);
+        if let Some(other) = parsed.iter().find(|option| option.field == field) {
+            let message = format!("Options {} and {} both become the field {}",
+                                  other.name,
+                                  name,
+                                  field);
+            return Err(JujuError::parse(&message, &name));
+        }
+        parsed.push(ConfigOption {
+            field,
+            name,
+            rust_type,
+            default,
+            description: option.get("description").and_then(|d| d.as_str()).map(String::from),
+        });
+    }
+    Ok(parsed)
+}
+
+/// Generate the Rust source for a `Config` struct holding every option in `config_yaml`.
+/// Options with a default become plain fields, options without one become `Option`s.
+/// # Failures
+/// Returns a JujuError if config_yaml is not valid YAML, uses an unknown option type, has
+/// a default that does not match its type or has two options that become the same field
+pub fn generate_config(config_yaml: &str) -> Result<String, JujuError> {
+    let options = parse_options(config_yaml)?;
+    let mut out = String::new();
+
+    // Writing to a String can not fail so the fmt::Results below are ignored
+    let _ = writeln!(out, "// Generated by juju::codegen from config.yaml.  Do not edit.");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "/// The charm configuration described by config.yaml");
+    let _ = writeln!(out, "#[derive(Clone, Debug, PartialEq)]");
+    let _ = writeln!(out, "pub struct Config {{");
+    for option in &options {
+        if let Some(ref description) = option.description {
+            for line in description.trim().lines() {
+                let _ = writeln!(out, "    /// {}", line.trim_end());
+            }
+        }
+        if let Some(ref default) = option.default {
+            if option.description.is_some() {
+                let _ = writeln!(out, "    ///");
+            }
+            let _ = writeln!(out, "    /// Defaults to `{}`", yaml_scalar(default)?);
+        }
+        let _ = writeln!(out, "    pub {}: {},", option.field, option.field_type());
+    }
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "impl Default for Config {{");
+    let _ = writeln!(out, "    fn default() -> Config {{");
+    let _ = writeln!(out, "        Config {{");
+    for option in &options {
+        let _ = writeln!(out, "            {}: {},", option.field, option.default_expr()?);
+    }
+    let _ = writeln!(out, "        }}");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "impl Config {{");
+    let _ = writeln!(out, "    /// Read the current charm configuration with config-get.");
+    let _ = writeln!(out, "    /// Options Juju does not report keep their config.yaml default.");
+    let _ = writeln!(out, "    #[allow(dead_code)]");
+    let _ = writeln!(out, "    pub fn load() -> Result<Config, ::juju::JujuError> {{");
+    let _ = writeln!(out, "        let values = ::juju::config_get_values()?;");
+    let _ = writeln!(out, "        let defaults = Config::default();");
+    let _ = writeln!(out, "        Ok(Config {{");
+    for option in &options {
+        let _ = writeln!(out,
+                         "            {field}: ::juju::codegen::config_field(&values, {name:?}, \
+                          defaults.{field})?,",
+                         field = option.field,
+                         name = option.name);
+    }
+    let _ = writeln!(out, "        }})");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "}}");
+    Ok(out)
+}
+
+/// Read `config_yaml` and write the generated `Config` struct to `out`.  Meant to be called
+/// from a build script with a path inside OUT_DIR.
+/// # Failures
+/// Returns a JujuError if either file can not be accessed or the config can not be converted
+pub fn write_config<P, Q>(config_yaml: P, out: Q) -> Result<(), JujuError>
+    where P: AsRef<Path>,
+          Q: AsRef<Path>
+{
+    let yaml = fs::read_to_string(config_yaml)?;
+    let source = generate_config(&yaml)?;
+    fs::write(out, source)?;
+    Ok(())
+}
+
+/// Used by generated code to read a single option, falling back to `default` when Juju has no
+/// value for it
+/// # Failures
+/// Returns a JujuError if the value Juju reports does not match the option's type
+pub fn config_field<T: DeserializeOwned>(values: &HashMap<String, serde_json::Value>,
+                                         name: &str,
+                                         default: T)
+                                         -> Result<T, JujuError> {
+    match values.get(name) {
+        None | Some(&serde_json::Value::Null) => Ok(default),
+        Some(value) => Ok(serde_json::from_value(value.clone())?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json;
+
+    use super::{config_field, field_name, generate_config};
+
+    const CONFIG_YAML: &str = r#"
+options:
+  brick_paths:
+    type: string
+    default: /mnt/brick1
+    description: |
+      Space separated list of paths to use as bricks.
+      Each path must already exist.
+  replication-level:
+    type: int
+    default: 3
+    description: Number of replicas
+  ssl:
+    type: boolean
+    default: false
+  timeout:
+    type: float
+    default: 2
+  sysctl:
+    type: string
+    description: YAML formatted sysctl settings
+"#;
+
+    #[test]
+    fn it_generates_typed_fields_with_docs() {
+        let source = generate_config(CONFIG_YAML).unwrap();
+        assert!(source.contains("    /// Space separated list of paths to use as bricks.\n    \
+                                 /// Each path must already exist.\n    ///\n    \
+                                 /// Defaults to `/mnt/brick1`\n    \
+                                 pub brick_paths: String,\n"));
+        assert!(source.contains("pub replication_level: i64,"));
+        assert!(source.contains("pub ssl: bool,"));
+        assert!(source.contains("pub timeout: f64,"));
+        assert!(source.contains("pub sysctl: Option<String>,"));
+    }
+
+    #[test]
+    fn it_generates_defaults_and_a_loader() {
+        let source = generate_config(CONFIG_YAML).unwrap();
+        assert!(source.contains("brick_paths: \"/mnt/brick1\".to_string(),"));
+        assert!(source.contains("replication_level: 3,"));
+        assert!(source.contains("timeout: 2.0,"));
+        assert!(source.contains("sysctl: None,"));
+        assert!(source.contains("replication_level: ::juju::codegen::config_field(&values, \
+                                 \"replication-level\", defaults.replication_level)?,"));
+    }
+
+    #[test]
+    fn it_rejects_defaults_of_the_wrong_type() {
+        let yaml = "options:\n  port:\n    type: int\n    default: eighty\n";
+        assert!(generate_config(yaml).is_err());
+        let yaml = "options:\n  port:\n    type: integer\n";
+        assert!(generate_config(yaml).is_err());
+    }
+
+    #[test]
+    fn it_rejects_options_that_become_the_same_field() {
+        let yaml = "options:\n  brick-paths:\n    type: string\n  brick_paths:\n    type: string\n";
+        let error = generate_config(yaml).unwrap_err().to_string();
+        assert!(error.contains("brick-paths"), "{}", error);
+        assert!(error.contains("brick_paths"), "{}", error);
+    }
+
+    #[test]
+    fn secret_options_are_strings() {
+        let yaml = "options:\n  admin-password:\n    type: secret\n";
+        let source = generate_config(yaml).unwrap();
+        assert!(source.contains("pub admin_password: Option<String>,"));
+    }
+
+    #[test]
+    fn it_makes_option_names_into_fields() {
+        assert_eq!(field_name("brick-paths"), "brick_paths");
+        assert_eq!(field_name("type"), "r#type");
+        assert_eq!(field_name("crate"), "crate_");
+        assert_eq!(field_name("self"), "self_");
+        assert_eq!(field_name("super"), "super_");
+        assert_eq!(field_name("9p-mount"), "_9p_mount");
+    }
+
+    #[test]
+    fn config_field_falls_back_to_the_default() {
+        let mut values = HashMap::new();
+        values.insert("port".to_string(), serde_json::Value::from(8080));
+        values.insert("name".to_string(), serde_json::Value::Null);
+        assert_eq!(config_field(&values, "port", 80i64).unwrap(), 8080);
+        assert_eq!(config_field(&values, "name", "web".to_string()).unwrap(), "web");
+        assert!(config_field(&values, "missing", true).unwrap());
+        assert!(config_field(&values, "port", false).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! The events Juju runs hooks for.
+//!
+//! A hook is named after the event that triggered it.  Events about an endpoint, storage or
+//! container have that name as a prefix, ie: `server-relation-changed`.  Parsing the hook name
+//! into a `HookEvent` makes it possible to match on the kind of event and the endpoint
+//! separately instead of comparing strings.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use JujuError;
+
+/// The event that caused the current hook to run
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum HookEvent {
+    Install,
+    Start,
+    Stop,
+    Remove,
+    ConfigChanged,
+    UpgradeCharm,
+    UpdateStatus,
+    LeaderElected,
+    LeaderSettingsChanged,
+    PreSeriesUpgrade,
+    PostSeriesUpgrade,
+    CollectMetrics,
+    RelationCreated { endpoint: String },
+    RelationJoined { endpoint: String },
+    RelationChanged { endpoint: String },
+    RelationDeparted { endpoint: String },
+    RelationBroken { endpoint: String },
+    StorageAttached { name: String },
+    StorageDetaching { name: String },
+    PebbleReady { container: String },
+    PebbleCustomNotice { container: String },
+    PebbleCheckFailed { container: String },
+    PebbleCheckRecovered { container: String },
+    SecretChanged,
+    SecretExpired,
+    SecretRemove,
+    SecretRotate,
+    /// A hook this library does not know about, by name
+    Other(String),
+}
+
+/// Hooks that are not about a particular endpoint, storage or container
+const SIMPLE: &[(&str, HookEvent)] = &[("install", HookEvent::Install),
+                                       ("start", HookEvent::Start),
+                                       ("stop", HookEvent::Stop),
+                                       ("remove", HookEvent::Remove),
+                                       ("config-changed", HookEvent::ConfigChanged),
+                                       ("upgrade-charm", HookEvent::UpgradeCharm),
+                                       ("update-status", HookEvent::UpdateStatus),
+                                       ("leader-elected", HookEvent::LeaderElected),
+                                       ("leader-settings-changed",
+                                        HookEvent::LeaderSettingsChanged),
+                                       ("pre-series-upgrade", HookEvent::PreSeriesUpgrade),
+                                       ("post-series-upgrade", HookEvent::PostSeriesUpgrade),
+                                       ("collect-metrics", HookEvent::CollectMetrics),
+                                       ("secret-changed", HookEvent::SecretChanged),
+                                       ("secret-expired", HookEvent::SecretExpired),
+                                       ("secret-remove", HookEvent::SecretRemove),
+                                       ("secret-rotate", HookEvent::SecretRotate)];
+
+/// Builds an event from the prefix of a hook name
+type PrefixedEvent = fn(String) -> HookEvent;
+
+/// Hooks named `<prefix><suffix>`
+const PREFIXED: &[(&str, PrefixedEvent)] =
+    &[("-relation-created", |endpoint| HookEvent::RelationCreated { endpoint }),
+      ("-relation-joined", |endpoint| HookEvent::RelationJoined { endpoint }),
+      ("-relation-changed", |endpoint| HookEvent::RelationChanged { endpoint }),
+      ("-relation-departed", |endpoint| HookEvent::RelationDeparted { endpoint }),
+      ("-relation-broken", |endpoint| HookEvent::RelationBroken { endpoint }),
+      ("-storage-attached", |name| HookEvent::StorageAttached { name }),
+      ("-storage-detaching", |name| HookEvent::StorageDetaching { name }),
+      ("-pebble-ready", |container| HookEvent::PebbleReady { container }),
+      ("-pebble-custom-notice", |container| HookEvent::PebbleCustomNotice { container }),
+      ("-pebble-check-failed", |container| HookEvent::PebbleCheckFailed { container }),
+      ("-pebble-check-recovered",
+       |container| HookEvent::PebbleCheckRecovered { container })];
+
+impl HookEvent {
+    /// The event for the hook that is running, from JUJU_HOOK_NAME or the name the binary was
+    /// called by
+    /// # Failures
+    /// Returns JujuError::Parse with the hook name if it is missing or can not be parsed
+    pub fn current() -> Result<HookEvent, JujuError> {
+        match ::hook_name() {
+            Some(name) => name.parse(),
+            None => Err(JujuError::parse("Unable to determine the hook name", "")),
+        }
+    }
+
+    /// The endpoint of a relation event, ie: server for server-relation-changed
+    pub fn endpoint(&self) -> Option<&str> {
+        match *self {
+            HookEvent::RelationCreated { ref endpoint } |
+            HookEvent::RelationJoined { ref endpoint } |
+            HookEvent::RelationChanged { ref endpoint } |
+            HookEvent::RelationDeparted { ref endpoint } |
+            HookEvent::RelationBroken { ref endpoint } => Some(endpoint),
+            _ => None,
+        }
+    }
+
+    /// The storage name of a storage event, ie: brick for brick-storage-attached
+    pub fn storage(&self) -> Option<&str> {
+        match *self {
+            HookEvent::StorageAttached { ref name } |
+            HookEvent::StorageDetaching { ref name } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The container name of a pebble event, ie: nginx for nginx-pebble-ready
+    pub fn container(&self) -> Option<&str> {
+        match *self {
+            HookEvent::PebbleReady { ref container } |
+            HookEvent::PebbleCustomNotice { ref container } |
+            HookEvent::PebbleCheckFailed { ref container } |
+            HookEvent::PebbleCheckRecovered { ref container } => Some(container),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = JujuError;
+
+    /// Parse a hook name.  Paths such as hooks/install are accepted, only the file name is used.
+    /// Unknown hook names parse as HookEvent::Other.
+    fn from_str(s: &str) -> Result<HookEvent, JujuError> {
+        let name = Path::new(s).file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name.is_empty() {
+            return Err(JujuError::parse("Invalid hook name", s));
+        }
+        if let Some((_, event)) = SIMPLE.iter().find(|&&(simple, _)| simple == name) {
+            return Ok(event.clone());
+        }
+        let event = PREFIXED.iter()
+            .filter(|&&(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+            .map(|&(suffix, event)| event(name[..name.len() - suffix.len()].to_string()))
+            .next()
+            .unwrap_or_else(|| HookEvent::Other(name.to_string()));
+        Ok(event)
+    }
+}
+
+impl fmt::Display for HookEvent {
+    /// Writes the name of the hook Juju runs for the event
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, suffix): (&str, &str) = match *self {
+            HookEvent::Install => ("", "install"),
+            HookEvent::Start => ("", "start"),
+            HookEvent::Stop => ("", "stop"),
+            HookEvent::Remove => ("", "remove"),
+            HookEvent::ConfigChanged => ("", "config-changed"),
+            HookEvent::UpgradeCharm => ("", "upgrade-charm"),
+            HookEvent::UpdateStatus => ("", "update-status"),
+            HookEvent::LeaderElected => ("", "leader-elected"),
+            HookEvent::LeaderSettingsChanged => ("", "leader-settings-changed"),
+            HookEvent::PreSeriesUpgrade => ("", "pre-series-upgrade"),
+            HookEvent::PostSeriesUpgrade => ("", "post-series-upgrade"),
+            HookEvent::CollectMetrics => ("", "collect-metrics"),
+            HookEvent::SecretChanged => ("", "secret-changed"),
+            HookEvent::SecretExpired => ("", "secret-expired"),
+            HookEvent::SecretRemove => ("", "secret-remove"),
+            HookEvent::SecretRotate => ("", "secret-rotate"),
+            HookEvent::RelationCreated { ref endpoint } => (endpoint, "-relation-created"),
+            HookEvent::RelationJoined { ref endpoint } => (endpoint, "-relation-joined"),
+            HookEvent::RelationChanged { ref endpoint } => (endpoint, "-relation-changed"),
+            HookEvent::RelationDeparted { ref endpoint } => (endpoint, "-relation-departed"),
+            HookEvent::RelationBroken { ref endpoint } => (endpoint, "-relation-broken"),
+            HookEvent::StorageAttached { ref name } => (name, "-storage-attached"),
+            HookEvent::StorageDetaching { ref name } => (name, "-storage-detaching"),
+            HookEvent::PebbleReady { ref container } => (container, "-pebble-ready"),
+            HookEvent::PebbleCustomNotice { ref container } => {
+                (container, "-pebble-custom-notice")
+            }
+            HookEvent::PebbleCheckFailed { ref container } => (container, "-pebble-check-failed"),
+            HookEvent::PebbleCheckRecovered { ref container } => {
+                (container, "-pebble-check-recovered")
+            }
+            HookEvent::Other(ref name) => (name, ""),
+        };
+        write!(f, "{}{}", prefix, suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HookEvent, SIMPLE};
+    use testing::FakeModel;
+    use JujuError;
+
+    #[test]
+    fn it_parses_hook_names() {
+        let parse = |name: &str| name.parse::<HookEvent>().unwrap();
+        assert_eq!(parse("install"), HookEvent::Install);
+        assert_eq!(parse("/var/lib/juju/charm/hooks/config-changed"),
+                   HookEvent::ConfigChanged);
+        assert_eq!(parse("brick-server-relation-changed"),
+                   HookEvent::RelationChanged { endpoint: "brick-server".to_string() });
+        assert_eq!(parse("brick-storage-attached"),
+                   HookEvent::StorageAttached { name: "brick".to_string() });
+        assert_eq!(parse("nginx-pebble-ready"),
+                   HookEvent::PebbleReady { container: "nginx".to_string() });
+        assert_eq!(parse("changed"), HookEvent::Other("changed".to_string()));
+        assert_eq!(parse("-relation-changed"),
+                   HookEvent::Other("-relation-changed".to_string()));
+        assert!("".parse::<HookEvent>().is_err());
+    }
+
+    #[test]
+    fn current_reports_an_unparseable_hook_name() {
+        let model = FakeModel::new("gluster/0");
+        let _runner = model.install();
+        model.set_env("JUJU_HOOK_NAME", "hooks/install");
+        assert_eq!(HookEvent::current().unwrap(), HookEvent::Install);
+        model.set_env("JUJU_HOOK_NAME", "/");
+        match HookEvent::current() {
+            Err(JujuError::Parse { ref text, .. }) => assert_eq!(text, "/"),
+            other => panic!("expected a Parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_displays_the_hook_name() {
+        for name in &["leader-settings-changed",
+                      "server-relation-departed",
+                      "data-storage-detaching",
+                      "web-pebble-check-recovered",
+                      "secret-rotate",
+                      "custom"] {
+            assert_eq!(name.parse::<HookEvent>().unwrap().to_string(), *name);
+        }
+        for &(name, ref event) in SIMPLE {
+            assert_eq!(event.to_string(), name);
+        }
+        let event = HookEvent::RelationJoined { endpoint: "server".to_string() };
+        assert_eq!(event.endpoint(), Some("server"));
+        assert_eq!(event.storage(), None);
+    }
+}
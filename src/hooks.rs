@@ -0,0 +1,902 @@
+//! Hook handlers and the registry that picks which of them to run.
+//!
+//! A `HookHandler` pairs a handler with the hooks it handles.  Besides exact hook names, a
+//! hook can be registered for glob patterns like `*-relation-changed` and for several hooks at
+//! once, so a charm that reacts the same way to config-changed, upgrade-charm and every
+//! relation change only needs a single registration.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::LogLevel;
+
+use runner::{self, HookToolRunner};
+use {Context, HookEvent, Status, StatusCollector, StatusType};
+
+/// The hooks that are about the unit or application itself rather than about a relation,
+/// storage, container or secret.  Register a hook with `HookHandler::on(LIFECYCLE_HOOKS, ...)` to
+/// run it for all of them.
+pub const LIFECYCLE_HOOKS: &[&str] = &["install",
+                                       "start",
+                                       "stop",
+                                       "remove",
+                                       "config-changed",
+                                       "upgrade-charm",
+                                       "update-status",
+                                       "leader-elected",
+                                       "leader-settings-changed",
+                                       "pre-series-upgrade",
+                                       "post-series-upgrade"];
+
+/// What a hook handler is given when it runs: the event being handled and the hook
+/// environment.  It dereferences to the `Context`, so `context.remote_unit` and
+/// `context.load_relations()` work directly.
+pub struct HookContext {
+    /// The event that caused the hook to run
+    pub event: HookEvent,
+    /// The hook environment
+    pub context: Context,
+    /// Statuses reported by the components of the charm.  `HookRegistry::run` sets the
+    /// combined status once the hook has run.
+    pub status: StatusCollector,
+    started: Instant,
+    runner: Option<Rc<dyn HookToolRunner>>,
+}
+
+impl HookContext {
+    /// Constructs a new `HookContext` for `event` from the hook environment
+    pub fn new(event: HookEvent) -> HookContext {
+        HookContext {
+            event,
+            context: Context::new_from_env(),
+            status: StatusCollector::new(),
+            started: Instant::now(),
+            runner: None,
+        }
+    }
+
+    /// Constructs a new `HookContext` for `event` whose hook tool calls all go to `runner`.
+    /// The hook environment is read through `runner`, and `HookRegistry::run` installs it
+    /// while the hook runs, so several contexts can each talk to their own fake on one thread.
+    pub fn with_runner(event: HookEvent, runner: Rc<dyn HookToolRunner>) -> HookContext {
+        let _guard = runner::install_shared_runner(runner.clone());
+        HookContext { runner: Some(runner), ..HookContext::new(event) }
+    }
+
+    /// How long ago the hook started running
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl fmt::Debug for HookContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HookContext")
+            .field("event", &self.event)
+            .field("context", &self.context)
+            .field("status", &self.status)
+            .field("started", &self.started)
+            .field("runner", &self.runner.is_some())
+            .finish()
+    }
+}
+
+impl Deref for HookContext {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        &self.context
+    }
+}
+
+impl DerefMut for HookContext {
+    fn deref_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
+}
+
+/// A boxed hook handler
+type BoxedHandler<E> = Box<dyn FnMut(&mut HookContext) -> Result<(), E>>;
+
+/// A boxed reconcile function
+type Reconcile<E> = Box<dyn FnMut(&Context) -> Result<(), E>>;
+
+/// A boxed before or after middleware
+type Middleware = Box<dyn FnMut(&mut HookContext) -> Result<(), String>>;
+
+/// A boxed error middleware
+type ErrorMiddleware = Box<dyn FnMut(&mut HookContext, String) -> Result<(), String>>;
+
+/// Anything that can handle a hook: closures and functions that take a `&mut HookContext`, and
+/// ones that take no arguments at all.  The `Marker` type only exists to tell the two apart.
+pub trait IntoHookHandler<Marker, E> {
+    /// Box the handler up so it can be stored in a `HookHandler`
+    fn into_handler(self) -> BoxedHandler<E>;
+}
+
+/// Marks handlers that take a `&mut HookContext`
+#[derive(Debug)]
+pub enum WithContext {}
+
+/// Marks handlers that take no arguments, such as `fn() -> Result<(), String>`
+#[derive(Debug)]
+pub enum WithoutContext {}
+
+impl<F, E> IntoHookHandler<WithContext, E> for F
+    where F: FnMut(&mut HookContext) -> Result<(), E> + 'static
+{
+    fn into_handler(self) -> BoxedHandler<E> {
+        Box::new(self)
+    }
+}
+
+impl<F, E> IntoHookHandler<WithoutContext, E> for F
+    where F: FnMut() -> Result<(), E> + 'static
+{
+    fn into_handler(mut self) -> BoxedHandler<E> {
+        Box::new(move |_: &mut HookContext| self())
+    }
+}
+
+/// Match `name` against `pattern`, where `*` in the pattern matches any run of characters
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // split always yields at least one part
+    let first = parts.next().unwrap_or("");
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let parts: Vec<&str> = parts.collect();
+    let last = match parts.split_last() {
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            last
+        }
+        // No * at all, so the pattern has to match exactly
+        None => return rest.is_empty(),
+    };
+    rest.ends_with(last)
+}
+
+/// A hook registered with a plain function, as built by the `hook!` macro.  It becomes a
+/// `HookHandler` when added to a `HookRegistry`.
+#[derive(Debug, PartialEq)]
+#[allow(unpredictable_function_pointer_comparisons)]
+pub struct Hook {
+    /// The name of the hook to call
+    pub name: String,
+    /// A function to call when Juju calls this hook
+    /// # Failures
+    /// Your function passed in needs to return a String on error so that users will
+    /// know what happened.  Ideally this should also be logged with juju::log
+    pub callback: fn() -> Result<(), String>,
+}
+
+/// A hook handler registered under the hooks it handles.  Unlike `Hook` the handler can be a
+/// closure, is given the `HookContext` and can return any error that can be displayed.
+/// Build one with `HookHandler::new` or `HookHandler::on`.
+pub struct HookHandler<E = String> {
+    /// The name of the hook to call, or the patterns it was registered for separated by `|`
+    pub name: String,
+    patterns: Vec<String>,
+    /// A function to call when Juju calls this hook
+    /// # Failures
+    /// Your function needs to return an error that can be displayed so that users will know
+    /// what happened.
+    callback: BoxedHandler<E>,
+}
+
+impl<E> HookHandler<E> {
+    /// Register `handler` for the hook `name`.  The handler is given the context of the hook
+    /// and may capture whatever state it needs.
+    /// # Examples
+    /// ```
+    /// extern crate juju;
+    ///
+    /// fn main() {
+    ///     let brick = "/mnt/brick1".to_string();
+    ///     let hook = juju::HookHandler::new("server-relation-changed", move |context| {
+    ///         let peer = context.load_relations().map_err(|e| e.to_string())?.get("hostname");
+    ///         juju::log(format!("{:?} can use {}", peer, brick), None);
+    ///         Ok::<(), String>(())
+    ///     });
+    ///     assert_eq!(hook.name, "server-relation-changed");
+    /// }
+    /// ```
+    pub fn new<F>(name: &str, handler: F) -> HookHandler<E>
+        where F: FnMut(&mut HookContext) -> Result<(), E> + 'static
+    {
+        HookHandler::from_handler(name, handler)
+    }
+
+    /// Register `handler` for every hook matching any of `patterns`.  A `*` in a pattern
+    /// matches any run of characters, so `*-relation-changed` is a change on any endpoint and
+    /// `server-relation-*` is every relation hook for the server endpoint.
+    /// # Examples
+    /// ```
+    /// extern crate juju;
+    ///
+    /// fn main() {
+    ///     let patterns = ["config-changed", "upgrade-charm", "*-relation-changed"];
+    ///     let hook = juju::HookHandler::on(&patterns, |_context| Ok::<(), String>(()));
+    ///     assert!(hook.matches(&"server-relation-changed".parse().unwrap()));
+    ///     assert!(!hook.matches(&"server-relation-joined".parse().unwrap()));
+    /// }
+    /// ```
+    pub fn on<F>(patterns: &[&str], handler: F) -> HookHandler<E>
+        where F: FnMut(&mut HookContext) -> Result<(), E> + 'static
+    {
+        HookHandler::from_handler_on(patterns, handler)
+    }
+
+    /// Register any kind of handler for the hook `name`, including a plain
+    /// `fn() -> Result<(), String>`
+    pub fn from_handler<M, H: IntoHookHandler<M, E>>(name: &str, handler: H) -> HookHandler<E> {
+        HookHandler::from_handler_on(&[name], handler)
+    }
+
+    /// Register any kind of handler for every hook matching any of `patterns`
+    pub fn from_handler_on<M, H: IntoHookHandler<M, E>>(patterns: &[&str],
+                                                        handler: H)
+                                                        -> HookHandler<E> {
+        HookHandler {
+            name: patterns.join("|"),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            callback: handler.into_handler(),
+        }
+    }
+
+    /// The patterns this hook is registered for
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Returns true if this hook should run for `event`
+    pub fn matches(&self, event: &HookEvent) -> bool {
+        let name = event.to_string();
+        self.patterns.iter().any(|pattern| glob_match(pattern, &name))
+    }
+
+    /// Run the handler with `context`
+    pub fn call(&mut self, context: &mut HookContext) -> Result<(), E> {
+        (self.callback)(context)
+    }
+}
+
+impl<E> fmt::Debug for HookHandler<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HookHandler").field("name", &self.name).finish()
+    }
+}
+
+impl From<Hook> for HookHandler<String> {
+    fn from(hook: Hook) -> HookHandler<String> {
+        HookHandler::from_handler(&hook.name, hook.callback)
+    }
+}
+
+/// Before middleware that logs the event and the hook environment at DEBUG
+pub fn log_hook_environment(context: &mut HookContext) -> Result<(), String> {
+    ::log(format!("Running {} with {:?}", context.event, context.context),
+          Some(LogLevel::Debug));
+    Ok(())
+}
+
+/// Before middleware that sets maintenance status as soon as the hook starts, so the unit
+/// shows as busy while it runs.  It is set straight away instead of being added to
+/// `context.status`, so it does not outrank what the components report, which replaces it
+/// once the hook has run.
+pub fn maintenance_on_entry(context: &mut HookContext) -> Result<(), String> {
+    ::status_set(Status {
+            status_type: StatusType::Maintenance,
+            message: format!("Running {}", context.event),
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// After middleware that reports active status in `context.status` once the hook has
+/// succeeded.  A blocked or waiting status already reported for the unit, ie: by a failed
+/// reconcile, is kept.
+pub fn active_on_success(context: &mut HookContext) -> Result<(), String> {
+    report_active(&mut context.status);
+    Ok(())
+}
+
+/// Report the unit as active unless something other than maintenance was already reported
+/// for it, so a blocked or waiting status is not hidden
+fn report_active(status: &mut StatusCollector) {
+    let replace = match status.get("") {
+        Some(status) => status.status_type == StatusType::Maintenance,
+        None => true,
+    };
+    if replace {
+        status.add("",
+                   Status {
+                       status_type: StatusType::Active,
+                       message: String::new(),
+                   });
+    }
+}
+
+/// After middleware that logs how long the hook took at INFO
+pub fn log_hook_duration(context: &mut HookContext) -> Result<(), String> {
+    let elapsed = context.elapsed();
+    ::log(format!("{} took {}.{:03}s",
+                  context.event,
+                  elapsed.as_secs(),
+                  elapsed.subsec_millis()),
+          Some(LogLevel::Info));
+    Ok(())
+}
+
+/// Error middleware that logs the error and turns it into blocked status in `context.status`
+/// with the error as the message.  The hook then succeeds, so Juju shows the blocked status
+/// instead of putting the unit in an error state.
+pub fn blocked_on_error(context: &mut HookContext, error: String) -> Result<(), String> {
+    ::log(format!("{} failed: {}", context.event, error),
+          Some(LogLevel::Error));
+    context.status.add("",
+                       Status {
+                           status_type: StatusType::Blocked,
+                           message: error,
+                       });
+    Ok(())
+}
+
+/// Every hook a charm handles.  When several hooks match an event they run in the order they
+/// were registered, stopping at the first error.  If none match, the fallback runs instead.
+///
+/// In reconcile mode, set up with `reconcile`, the matching hooks are only pre-handlers for
+/// hook specific work such as cleaning up after a departed unit.  A single reconcile function
+/// then runs for every hook and converges the unit to its desired state.
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let mut registry: juju::HookRegistry = juju::HookRegistry::new();
+///     registry.on(juju::LIFECYCLE_HOOKS, |_context| {
+///             juju::log("Reconfiguring", None);
+///             Ok(())
+///         })
+///         .on(&["server-relation-*"], |context| {
+///             juju::log(format!("Peer event {}", context.event), None);
+///             Ok(())
+///         })
+///         .fallback(|context| {
+///             juju::log(format!("Ignoring {}", context.event), None);
+///             Ok(())
+///         });
+///     // In a real charm:
+///     // juju::process_hooks(registry).unwrap();
+/// }
+/// ```
+pub struct HookRegistry<E = String> {
+    hooks: Vec<HookHandler<E>>,
+    fallback: Option<BoxedHandler<E>>,
+    reconcile: Option<Reconcile<E>>,
+    before: Vec<Middleware>,
+    after: Vec<Middleware>,
+    on_error: Vec<ErrorMiddleware>,
+}
+
+impl<E> HookRegistry<E> {
+    /// Constructs an empty `HookRegistry`
+    pub fn new() -> HookRegistry<E> {
+        HookRegistry {
+            hooks: Vec::new(),
+            fallback: None,
+            reconcile: None,
+            before: Vec::new(),
+            after: Vec::new(),
+            on_error: Vec::new(),
+        }
+    }
+
+    /// Run `middleware` before the handlers of every hook, in the order the middleware was
+    /// added.  If it fails, the handlers are skipped and the error goes to the error
+    /// middleware.
+    pub fn before<F>(&mut self, middleware: F) -> &mut HookRegistry<E>
+        where F: FnMut(&mut HookContext) -> Result<(), String> + 'static
+    {
+        self.before.push(Box::new(middleware));
+        self
+    }
+
+    /// Run `middleware` after the handlers of every hook succeed, in the order the middleware
+    /// was added
+    pub fn after<F>(&mut self, middleware: F) -> &mut HookRegistry<E>
+        where F: FnMut(&mut HookContext) -> Result<(), String> + 'static
+    {
+        self.after.push(Box::new(middleware));
+        self
+    }
+
+    /// Run `middleware` with the error when a before middleware, handler or after middleware
+    /// fails.  Error middleware runs in the order it was added, each one given the error the
+    /// last returned.  Returning Ok handles the error: the hook succeeds and the remaining
+    /// error middleware is skipped.
+    /// # Examples
+    /// ```
+    /// extern crate juju;
+    ///
+    /// fn main() {
+    ///     let model = juju::testing::FakeModel::new("gluster/0");
+    ///     let mut registry: juju::HookRegistry = juju::HookRegistry::new();
+    ///     registry.before(juju::hooks::maintenance_on_entry)
+    ///         .on(&["config-changed"], |_context| Err("no bricks configured".to_string()))
+    ///         .after(juju::hooks::active_on_success)
+    ///         .on_error(juju::hooks::blocked_on_error);
+    ///
+    ///     model.run_hook("config-changed", registry).unwrap();
+    ///     assert_eq!(model.status(),
+    ///                Some(("blocked".to_string(), "no bricks configured".to_string())));
+    /// }
+    /// ```
+    pub fn on_error<F>(&mut self, middleware: F) -> &mut HookRegistry<E>
+        where F: FnMut(&mut HookContext, String) -> Result<(), String> + 'static
+    {
+        self.on_error.push(Box::new(middleware));
+        self
+    }
+
+    /// Switch to reconcile mode: after any matching pre-handlers, `handler` runs for every hook
+    /// whichever hook fired.  Its outcome is added to `context.status`: active when it succeeds,
+    /// unless a handler already reported the unit blocked or waiting, and blocked with the
+    /// error as the message when it fails.  That way it is set along with the other component
+    /// statuses once the hook has run and after middleware such as `active_on_success` can not
+    /// hide a failure.  A failed reconcile does not fail the hook, since retrying it would not
+    /// help until the operator acts.
+    /// # Examples
+    /// ```
+    /// extern crate juju;
+    ///
+    /// fn main() {
+    ///     let model = juju::testing::FakeModel::new("gluster/0");
+    ///     model.set_config("brick_paths", "");
+    ///
+    ///     let mut registry: juju::HookRegistry = juju::HookRegistry::new();
+    ///     registry.reconcile(|_context| {
+    ///         let bricks = juju::config_get("brick_paths").map_err(|e| e.to_string())?;
+    ///         if bricks.trim().is_empty() {
+    ///             return Err("brick_paths must be set".to_string());
+    ///         }
+    ///         Ok(())
+    ///     });
+    ///
+    ///     model.run_hook("update-status", registry).unwrap();
+    ///     assert_eq!(model.status(),
+    ///                Some(("blocked".to_string(), "brick_paths must be set".to_string())));
+    /// }
+    /// ```
+    pub fn reconcile<F>(&mut self, handler: F) -> &mut HookRegistry<E>
+        where F: FnMut(&Context) -> Result<(), E> + 'static
+    {
+        self.reconcile = Some(Box::new(handler));
+        self
+    }
+
+    /// Add `hook` after every hook registered so far
+    pub fn register(&mut self, hook: HookHandler<E>) -> &mut HookRegistry<E> {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Register `handler` for every hook matching any of `patterns`.  See `HookHandler::on`.
+    pub fn on<F>(&mut self, patterns: &[&str], handler: F) -> &mut HookRegistry<E>
+        where F: FnMut(&mut HookContext) -> Result<(), E> + 'static
+    {
+        self.register(HookHandler::on(patterns, handler))
+    }
+
+    /// Run `handler` for any hook no registered hook matches, instead of failing
+    pub fn fallback<F>(&mut self, handler: F) -> &mut HookRegistry<E>
+        where F: FnMut(&mut HookContext) -> Result<(), E> + 'static
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// The hooks that would run for `event`, in the order they would run
+    pub fn matching(&self, event: &HookEvent) -> Vec<&HookHandler<E>> {
+        self.hooks.iter().filter(|hook| hook.matches(event)).collect()
+    }
+
+    /// Run every hook matching the event in `context`, or the fallback if none match, wrapped
+    /// in the middleware.  Afterwards, once the error middleware has run, the statuses added to
+    /// `context.status` are combined and set with a single status-set.
+    /// # Failures
+    /// Returns the first error from a middleware or handler, or an error if nothing handles the
+    /// event, unless the error middleware handles it
+    pub fn run(&mut self, context: &mut HookContext) -> Result<(), String>
+        where E: fmt::Display
+    {
+        let _guard = context.runner.clone().map(runner::install_shared_runner);
+        let mut result = self.run_with_middleware(context);
+        for on_error in &mut self.on_error {
+            match result {
+                Ok(()) => break,
+                Err(e) => result = on_error(context, e),
+            }
+        }
+        // Set whatever was reported once, even if the hook failed, so the status does not
+        // flap between the components and the middleware
+        let committed = context.status.commit().map(|_| ()).map_err(|e| e.to_string());
+        result.and(committed)
+    }
+
+    fn run_with_middleware(&mut self, context: &mut HookContext) -> Result<(), String>
+        where E: fmt::Display
+    {
+        for before in &mut self.before {
+            before(context)?;
+        }
+        self.run_handlers(context)?;
+        for after in &mut self.after {
+            after(context)?;
+        }
+        Ok(())
+    }
+
+    fn run_handlers(&mut self, context: &mut HookContext) -> Result<(), String>
+        where E: fmt::Display
+    {
+        let event = context.event.clone();
+        let mut handled = false;
+        for hook in self.hooks.iter_mut().filter(|hook| hook.matches(&event)) {
+            handled = true;
+            hook.call(context).map_err(|e| e.to_string())?;
+        }
+        if let Some(ref mut reconcile) = self.reconcile {
+            match reconcile(&context.context) {
+                Ok(()) => report_active(&mut context.status),
+                Err(e) => {
+                    ::log(format!("Reconcile failed in {}: {}", event, e),
+                          Some(LogLevel::Error));
+                    context.status.add("",
+                                       Status {
+                                           status_type: StatusType::Blocked,
+                                           message: e.to_string(),
+                                       });
+                }
+            }
+            return Ok(());
+        }
+        if handled {
+            return Ok(());
+        }
+        match self.fallback {
+            Some(ref mut fallback) => fallback(context).map_err(|e| e.to_string()),
+            None => Err(format!("Warning: Unknown callback for hook {}", context.event)),
+        }
+    }
+}
+
+impl<E> Default for HookRegistry<E> {
+    fn default() -> HookRegistry<E> {
+        HookRegistry::new()
+    }
+}
+
+impl<E> From<Vec<HookHandler<E>>> for HookRegistry<E> {
+    fn from(hooks: Vec<HookHandler<E>>) -> HookRegistry<E> {
+        HookRegistry { hooks, ..HookRegistry::new() }
+    }
+}
+
+impl From<Vec<Hook>> for HookRegistry<String> {
+    fn from(hooks: Vec<Hook>) -> HookRegistry<String> {
+        hooks.into_iter().map(HookHandler::from).collect::<Vec<HookHandler>>().into()
+    }
+}
+
+impl<E> fmt::Debug for HookRegistry<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HookRegistry")
+            .field("hooks", &self.hooks)
+            .field("fallback", &self.fallback.is_some())
+            .field("reconcile", &self.reconcile.is_some())
+            .field("before", &self.before.len())
+            .field("after", &self.after.len())
+            .field("on_error", &self.on_error.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use {HookEvent, Status, StatusType};
+    use testing::FakeModel;
+    use super::{active_on_success, glob_match, maintenance_on_entry, HookContext, HookRegistry,
+                LIFECYCLE_HOOKS};
+
+    #[test]
+    fn it_matches_globs() {
+        assert!(glob_match("config-changed", "config-changed"));
+        assert!(!glob_match("changed", "config-changed"));
+        assert!(glob_match("*-relation-changed", "server-relation-changed"));
+        assert!(!glob_match("*-relation-changed", "server-relation-joined"));
+        assert!(glob_match("server-*", "server-relation-broken"));
+        assert!(glob_match("*-relation-*", "db-admin-relation-departed"));
+        assert!(glob_match("*", "install"));
+        assert!(!glob_match("server-*-changed", "server-changed"));
+    }
+
+    #[test]
+    fn it_runs_every_matching_hook_in_registration_order() {
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let mut registry: HookRegistry = HookRegistry::new();
+        for &(name, patterns) in &[("lifecycle", LIFECYCLE_HOOKS),
+                                   ("any-changed", &["*-relation-changed"][..]),
+                                   ("server", &["server-relation-*", "config-changed"][..])] {
+            let ran = ran.clone();
+            registry.on(patterns, move |_| {
+                ran.borrow_mut().push(name);
+                Ok(())
+            });
+        }
+        let mut run = |hook: &str| {
+            ran.borrow_mut().clear();
+            let mut context = HookContext::new(hook.parse::<HookEvent>().unwrap());
+            let result = registry.run(&mut context);
+            (result, ran.borrow().clone())
+        };
+
+        assert_eq!(run("config-changed"), (Ok(()), vec!["lifecycle", "server"]));
+        assert_eq!(run("server-relation-changed"), (Ok(()), vec!["any-changed", "server"]));
+        assert_eq!(run("db-relation-changed"), (Ok(()), vec!["any-changed"]));
+        assert_eq!(run("db-relation-joined"),
+                   (Err("Warning: Unknown callback for hook db-relation-joined".to_string()),
+                    vec![]));
+    }
+
+    #[test]
+    fn reconcile_runs_for_every_hook_after_the_pre_handlers() {
+        let model = FakeModel::new("gluster/0");
+        let id = model.add_relation("server", "gluster");
+        model.add_relation_unit(&id, "gluster/1");
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let registry = || {
+            let (cleanup, reconcile) = (ran.clone(), ran.clone());
+            let mut registry: HookRegistry = HookRegistry::new();
+            registry.on(&["*-relation-departed"], move |context| {
+                    let unit = context.remote_unit.as_ref().map(|u| u.to_string());
+                    cleanup.borrow_mut().push(format!("cleanup {}", unit.unwrap_or_default()));
+                    Ok(())
+                })
+                .reconcile(move |context| {
+                    reconcile.borrow_mut().push("reconcile".to_string());
+                    match context.relation {
+                        Some(_) => Ok(()),
+                        None => Err("waiting for peers".to_string()),
+                    }
+                });
+            registry
+        };
+
+        model.run_relation_hook("server-relation-departed", &id, "gluster/1", registry())
+            .unwrap();
+        assert_eq!(*ran.borrow(), vec!["cleanup gluster/1", "reconcile"]);
+        assert_eq!(model.status(), Some(("active".to_string(), String::new())));
+
+        model.run_hook("config-changed", registry()).unwrap();
+        assert_eq!(ran.borrow().len(), 3);
+        assert_eq!(model.status(),
+                   Some(("blocked".to_string(), "waiting for peers".to_string())));
+    }
+
+    #[test]
+    fn active_on_success_does_not_hide_a_failed_reconcile() {
+        let model = FakeModel::new("gluster/0");
+        let registry = |bricks: &'static str| {
+            let mut registry: HookRegistry = HookRegistry::new();
+            registry.reconcile(move |_| if bricks.is_empty() {
+                    Err("brick_paths must be set".to_string())
+                } else {
+                    Ok(())
+                })
+                .after(active_on_success);
+            registry
+        };
+
+        model.run_hook("config-changed", registry("")).unwrap();
+        assert_eq!(model.status(),
+                   Some(("blocked".to_string(), "brick_paths must be set".to_string())));
+        model.run_hook("config-changed", registry("/mnt/brick1")).unwrap();
+        assert_eq!(model.status(), Some(("active".to_string(), String::new())));
+    }
+
+    #[test]
+    fn a_successful_reconcile_keeps_the_status_a_handler_reported() {
+        let model = FakeModel::new("gluster/0");
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.on(&["config-changed"], |context| {
+                context.status.add("",
+                                   Status {
+                                       status_type: StatusType::Blocked,
+                                       message: "brick_paths must be absolute".to_string(),
+                                   });
+                Ok(())
+            })
+            .reconcile(|_| Ok(()));
+
+        model.run_hook("config-changed", registry).unwrap();
+        assert_eq!(model.status(),
+                   Some(("blocked".to_string(), "brick_paths must be absolute".to_string())));
+    }
+
+    #[test]
+    fn middleware_wraps_the_handlers() {
+        let model = FakeModel::new("gluster/0");
+        let ran = Rc::new(RefCell::new(Vec::new()));
+        let registry = |fail: &'static str| {
+            let mut registry: HookRegistry = HookRegistry::new();
+            let (before, handler, after) = (ran.clone(), ran.clone(), ran.clone());
+            let (first, second) = (ran.clone(), ran.clone());
+            registry.before(move |context| {
+                    before.borrow_mut().push(format!("before {}", context.event));
+                    if fail == "before" { Err("before failed".to_string()) } else { Ok(()) }
+                })
+                .on(&["install"], move |_| {
+                    handler.borrow_mut().push("handler".to_string());
+                    if fail == "handler" { Err("handler failed".to_string()) } else { Ok(()) }
+                })
+                .after(move |_| {
+                    after.borrow_mut().push("after".to_string());
+                    Ok(())
+                })
+                .on_error(move |_, e| {
+                    first.borrow_mut().push(format!("first {}", e));
+                    Err(format!("wrapped {}", e))
+                })
+                .on_error(move |_, e| {
+                    second.borrow_mut().push(format!("second {}", e));
+                    if fail == "handler" { Ok(()) } else { Err(e) }
+                });
+            registry
+        };
+
+        model.run_hook("install", registry("")).unwrap();
+        assert_eq!(*ran.borrow(), vec!["before install", "handler", "after"]);
+
+        ran.borrow_mut().clear();
+        model.run_hook("install", registry("handler")).unwrap();
+        assert_eq!(*ran.borrow(),
+                   vec!["before install",
+                        "handler",
+                        "first handler failed",
+                        "second wrapped handler failed"]);
+
+        ran.borrow_mut().clear();
+        assert_eq!(model.run_hook("install", registry("before")),
+                   Err("wrapped before failed".to_string()));
+        assert_eq!(*ran.borrow(),
+                   vec!["before install", "first before failed", "second wrapped before failed"]);
+    }
+
+    #[test]
+    fn it_runs_the_fallback_when_nothing_matches() {
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.on(&["install"], |_| Err("install failed".to_string()))
+            .on(&["install"], |_| panic!("runs after a failure"))
+            .fallback(|context| Err(format!("fallback for {}", context.event)));
+
+        let mut context = HookContext::new(HookEvent::Install);
+        assert_eq!(registry.run(&mut context), Err("install failed".to_string()));
+        let mut context = HookContext::new(HookEvent::UpdateStatus);
+        assert_eq!(registry.run(&mut context),
+                   Err("fallback for update-status".to_string()));
+    }
+
+    #[test]
+    fn a_context_runner_answers_the_calls_made_by_its_hook() {
+        let (first, second) = (FakeModel::new("gluster/0"), FakeModel::new("web/3"));
+        first.set_config("name", "first");
+        second.set_config("name", "second");
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.on(&["config-changed"], |context| {
+            let name = ::config_get("name").map_err(|e| e.to_string())?;
+            let unit = context.unit.clone();
+            ::status_set(Status {
+                    status_type: StatusType::Active,
+                    message: format!("{} on {}", name, unit),
+                })
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        });
+
+        for model in &[&first, &second] {
+            let mut context = HookContext::with_runner(HookEvent::ConfigChanged,
+                                                       Rc::new((*model).clone()));
+            registry.run(&mut context).unwrap();
+        }
+        assert_eq!(first.status(),
+                   Some(("active".to_string(), "first on gluster/0".to_string())));
+        assert_eq!(second.status(),
+                   Some(("active".to_string(), "second on web/3".to_string())));
+    }
+
+    #[test]
+    fn component_statuses_are_set_once_after_the_hook() {
+        let model = FakeModel::new("gluster/0");
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.on(&["update-status"], |context| {
+                context.status.add("storage",
+                                   Status {
+                                       status_type: StatusType::Waiting,
+                                       message: "no bricks yet".to_string(),
+                                   });
+                Ok(())
+            })
+            .on(&["update-status"], |context| {
+                context.status.add("workload",
+                                   Status {
+                                       status_type: StatusType::Active,
+                                       message: "serving".to_string(),
+                                   });
+                Ok(())
+            })
+            .after(active_on_success);
+
+        model.run_hook("update-status", registry).unwrap();
+        assert_eq!(model.status(),
+                   Some(("waiting".to_string(), "storage: no bricks yet".to_string())));
+        let status_sets = model.calls().into_iter().filter(|(tool, _)| tool == "status-set");
+        assert_eq!(status_sets.count(), 1);
+    }
+
+    #[test]
+    fn maintenance_shows_while_the_hook_runs() {
+        let model = FakeModel::new("gluster/0");
+        let running = model.clone();
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.before(maintenance_on_entry)
+            .on(&["config-changed"], move |_| {
+                assert_eq!(running.status(),
+                           Some(("maintenance".to_string(),
+                                 "Running config-changed".to_string())));
+                Ok(())
+            });
+
+        model.run_hook("config-changed", registry).unwrap();
+    }
+
+    #[test]
+    fn maintenance_on_entry_does_not_hide_component_statuses() {
+        let model = FakeModel::new("gluster/0");
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.before(maintenance_on_entry)
+            .on(&["update-status"], |context| {
+                context.status.add("peers",
+                                   Status {
+                                       status_type: StatusType::Waiting,
+                                       message: "2 of 3 peers".to_string(),
+                                   });
+                Ok(())
+            });
+
+        model.run_hook("update-status", registry).unwrap();
+        assert_eq!(model.status(),
+                   Some(("waiting".to_string(), "peers: 2 of 3 peers".to_string())));
+        let status_sets: Vec<Vec<String>> = model.calls()
+            .into_iter()
+            .filter(|(tool, _)| tool == "status-set")
+            .map(|(_, args)| args)
+            .collect();
+        assert_eq!(status_sets,
+                   vec![vec!["maintenance".to_string(), "Running update-status".to_string()],
+                        vec!["waiting".to_string(), "peers: 2 of 3 peers".to_string()]]);
+    }
+}
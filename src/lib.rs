@@ -47,21 +47,62 @@
 //! 16:16:05 INFO unit.hello-world/0.juju-log server.go:254 Hello Juju from Rust!`
 //!
 
-extern crate charmhelpers;
 extern crate log;
-
-use std::collections::HashMap;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::error::Error;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::process;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::str::FromStr;
 use std::net::IpAddr;
-use std::io;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
 
 use log::LogLevel;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
-pub use charmhelpers::core::hookenv::log;
-
+#[macro_use]
 pub mod macros;
+pub mod codegen;
+pub mod events;
+pub mod hooks;
+pub mod logger;
+pub mod names;
+pub mod network;
+pub mod ports;
+pub mod runner;
+pub mod secrets;
+pub mod status;
+pub mod testing;
+
+pub use events::HookEvent;
+pub use hooks::{Hook, HookContext, HookHandler, HookRegistry, IntoHookHandler, LIFECYCLE_HOOKS,
+                WithContext, WithoutContext};
+pub use names::{ApplicationName, RelationId, UnitName};
+pub use network::{network_get, network_get_by_id, BindAddress, InterfaceAddress, NetworkInfo};
+pub use ports::{close_port_on, open_port_on, opened_ports, opened_ports_by_endpoint, set_ports,
+                Port};
+pub use runner::{HookToolRunner, ProcessRunner, ToolOutput};
+pub use secrets::{secret_add, secret_get, secret_get_by_label, secret_grant, secret_grant_unit,
+                  secret_ids, secret_info_get, secret_info_get_by_label, secret_peek,
+                  secret_refresh, secret_remove, secret_remove_revision, secret_revoke,
+                  secret_revoke_unit, secret_set, RotatePolicy, SecretContent, SecretInfo,
+                  SecretOptions, SecretOwner, SecretUri};
+pub use status::StatusCollector;
 
 // Custom error handling for the library
 #[derive(Debug)]
@@ -71,24 +112,101 @@ pub enum JujuError {
     ParseIntError(std::num::ParseIntError),
     VarError(std::env::VarError),
     AddrParseError(std::net::AddrParseError),
+    JsonError(serde_json::Error),
+    YamlError(serde_yaml::Error),
+    /// A leader-only operation was attempted on a unit that is not the leader
+    NotLeader,
+    /// A hook tool ran but did not succeed
+    CommandFailed {
+        /// The hook tool, ie: relation-get
+        tool: String,
+        /// The arguments it was run with
+        args: Vec<String>,
+        /// The exit code, or None if the tool was killed by a signal
+        exit_code: Option<i32>,
+        /// Everything the tool wrote to stderr
+        stderr: String,
+    },
+    /// A hook tool could not be found.  This usually means the charm is not running in a hook
+    /// context, so Juju has not put its tools on the PATH.
+    ToolNotFound { tool: String },
+    /// Text from Juju or the charm could not be parsed
+    Parse {
+        /// What was wrong with the text
+        message: String,
+        /// The text that could not be parsed
+        text: String,
+    },
 }
 
 impl JujuError {
-    fn new(err: String) -> JujuError {
-        JujuError::IoError(io::Error::new(std::io::ErrorKind::Other, err))
+    fn parse(message: &str, text: &str) -> JujuError {
+        JujuError::Parse {
+            message: message.to_string(),
+            text: text.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for JujuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JujuError::IoError(ref err) => err.fmt(f),
+            JujuError::FromUtf8Error(ref err) => err.fmt(f),
+            JujuError::ParseIntError(ref err) => err.fmt(f),
+            JujuError::VarError(ref err) => err.fmt(f),
+            JujuError::AddrParseError(ref err) => err.fmt(f),
+            JujuError::JsonError(ref err) => err.fmt(f),
+            JujuError::YamlError(ref err) => err.fmt(f),
+            JujuError::NotLeader => f.write_str("This unit is not the leader"),
+            JujuError::CommandFailed { ref tool, ref args, exit_code, ref stderr } => {
+                write!(f, "{}", tool)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                match exit_code {
+                    Some(code) => write!(f, " failed with exit code {}", code)?,
+                    None => f.write_str(" was terminated by a signal")?,
+                }
+                let stderr = stderr.trim();
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+            JujuError::ToolNotFound { ref tool } => {
+                write!(f, "{} was not found.  Is this running in a Juju hook context?", tool)
+            }
+            JujuError::Parse { ref message, ref text } => write!(f, "{}: {:?}", message, text),
+        }
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl error::Error for JujuError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            JujuError::IoError(ref err) => err.description().to_string(),
-            JujuError::FromUtf8Error(ref err) => err.description().to_string(),
-            JujuError::ParseIntError(ref err) => err.description().to_string(),
-            JujuError::VarError(ref err) => err.description().to_string(),
-            JujuError::AddrParseError(ref err) => err.description().to_string(),
+            JujuError::IoError(ref err) => Some(err),
+            JujuError::FromUtf8Error(ref err) => Some(err),
+            JujuError::ParseIntError(ref err) => Some(err),
+            JujuError::VarError(ref err) => Some(err),
+            JujuError::AddrParseError(ref err) => Some(err),
+            JujuError::JsonError(ref err) => Some(err),
+            JujuError::YamlError(ref err) => Some(err),
+            JujuError::NotLeader |
+            JujuError::CommandFailed { .. } |
+            JujuError::ToolNotFound { .. } |
+            JujuError::Parse { .. } => None,
         }
     }
 }
 
+/// Lets hook handlers returning `Result<(), String>` use `?` on library calls
+impl From<JujuError> for String {
+    fn from(err: JujuError) -> String {
+        err.to_string()
+    }
+}
+
 impl From<io::Error> for JujuError {
     fn from(err: io::Error) -> JujuError {
         JujuError::IoError(err)
@@ -119,46 +237,86 @@ impl From<std::net::AddrParseError> for JujuError {
     }
 }
 
+impl From<serde_json::Error> for JujuError {
+    fn from(err: serde_json::Error) -> JujuError {
+        JujuError::JsonError(err)
+    }
+}
 
-#[derive(Debug)]
+impl From<serde_yaml::Error> for JujuError {
+    fn from(err: serde_yaml::Error) -> JujuError {
+        JujuError::YamlError(err)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Transport {
     Tcp,
     Udp,
 }
 
-impl Transport {
-    /// Returns a String representation of the enum variant
-    fn to_string(self) -> String {
-        match self {
-            Transport::Tcp => "tcp".to_string(),
-            Transport::Udp => "udp".to_string(),
+impl fmt::Display for Transport {
+    /// Writes the lowercase name Juju uses for the transport
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Udp => write!(f, "udp"),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 /// For information about what these StatusType variants mean see: [Status reference]
 /// (https://jujucharms.com/docs/stable/reference-status)
+/// Charms can only set Maintenance, Waiting, Active and Blocked.  The others are set by Juju
+/// and show up in status_get_info.
 pub enum StatusType {
     Maintenance,
     Waiting,
     Active,
     Blocked,
+    /// A hook failed
+    Error,
+    /// The charm has not set a status yet
+    Unknown,
+    /// The unit is being destroyed
+    Terminated,
 }
 
-impl StatusType {
-    /// Returns a String representation of the enum variant
-    pub fn to_string(self) -> String {
-        match self {
-            StatusType::Maintenance => "maintenance".to_string(),
-            StatusType::Waiting => "waiting".to_string(),
-            StatusType::Active => "active".to_string(),
-            StatusType::Blocked => "blocked".to_string(),
+impl fmt::Display for StatusType {
+    /// Writes the lowercase name Juju uses for the status
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StatusType::Maintenance => write!(f, "maintenance"),
+            StatusType::Waiting => write!(f, "waiting"),
+            StatusType::Active => write!(f, "active"),
+            StatusType::Blocked => write!(f, "blocked"),
+            StatusType::Error => write!(f, "error"),
+            StatusType::Unknown => write!(f, "unknown"),
+            StatusType::Terminated => write!(f, "terminated"),
         }
     }
 }
 
-#[derive(Debug)]
+impl FromStr for StatusType {
+    type Err = JujuError;
+
+    fn from_str(s: &str) -> Result<StatusType, JujuError> {
+        match s {
+            "maintenance" => Ok(StatusType::Maintenance),
+            "waiting" => Ok(StatusType::Waiting),
+            "active" => Ok(StatusType::Active),
+            "blocked" => Ok(StatusType::Blocked),
+            "error" => Ok(StatusType::Error),
+            "unknown" => Ok(StatusType::Unknown),
+            "terminated" => Ok(StatusType::Terminated),
+            _ => Err(JujuError::parse("Invalid status", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Status {
     /// The type of status
     pub status_type: StatusType,
@@ -166,16 +324,126 @@ pub struct Status {
     pub message: String,
 }
 
+/// A status as status-get reports it
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusInfo {
+    /// The status and its message
+    pub status: Status,
+    /// Extra data Juju keeps with the status
+    pub data: HashMap<String, serde_json::Value>,
+    /// The status of every unit, for the application status.  Empty for a unit status.
+    pub units: HashMap<UnitName, StatusInfo>,
+}
+
+/// status-get output before the status and unit names are parsed
+#[derive(Deserialize)]
+struct RawStatus {
+    status: String,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "status-data", default)]
+    data: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default)]
+    units: HashMap<String, RawStatus>,
+}
+
+impl RawStatus {
+    fn parse(self) -> Result<StatusInfo, JujuError> {
+        let units = self.units
+            .into_iter()
+            .map(|(unit, status)| Ok((unit.parse()?, status.parse()?)))
+            .collect::<Result<_, JujuError>>()?;
+        Ok(StatusInfo {
+            status: Status {
+                status_type: self.status.parse()?,
+                message: self.message,
+            },
+            data: self.data.unwrap_or_default(),
+            units,
+        })
+    }
+}
+
+/// Proxy settings from the model config, for charms that download things
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProxySettings {
+    /// The proxy for http traffic
+    pub http_proxy: Option<String>,
+    /// The proxy for https traffic
+    pub https_proxy: Option<String>,
+    /// The proxy for ftp traffic
+    pub ftp_proxy: Option<String>,
+    /// Comma separated hosts and domains that should not be proxied
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    fn from_env() -> ProxySettings {
+        // Juju 2.9 added the JUJU_CHARM_ variables, older models only set the plain ones
+        let proxy = |name: &str| {
+            runner::var(&format!("JUJU_CHARM_{}", name.to_uppercase()))
+                .or_else(|_| runner::var(name))
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        ProxySettings {
+            http_proxy: proxy("http_proxy"),
+            https_proxy: proxy("https_proxy"),
+            ftp_proxy: proxy("ftp_proxy"),
+            no_proxy: proxy("no_proxy"),
+        }
+    }
+}
+
+/// Everything Juju tells a hook about why it is running, read from the hook environment.
+/// Variables that are not set for the current hook, or do not parse, are None.
 #[derive(Debug)]
 pub struct Context {
-    /// The scope for the current relation hook
+    /// The scope for the current relation hook, empty outside of relation hooks
     pub relation_type: String,
-    /// The relation ID for the current relation hook
+    /// The relation ID for the current relation hook, 0 outside of relation hooks
     pub relation_id: usize,
     /// Local unit ID
     pub unit: String,
-    /// relation data for all related units
+    /// relation data of the remote unit of the current relation hook.  Empty until
+    /// `load_relations` is called.
     pub relations: HashMap<String, String>,
+    /// The local endpoint name of the current relation hook, ie: server
+    pub endpoint: Option<String>,
+    /// The relation ID for the current relation hook
+    pub relation: Option<RelationId>,
+    /// Local unit name
+    pub unit_name: Option<UnitName>,
+    /// The remote unit that triggered the current relation hook
+    pub remote_unit: Option<UnitName>,
+    /// The remote application of the current relation hook
+    pub remote_app: Option<ApplicationName>,
+    /// The unit leaving the relation in a relation-departed hook
+    pub departing_unit: Option<UnitName>,
+    /// The name of the model the unit is deployed in
+    pub model_name: Option<String>,
+    /// The UUID of the model the unit is deployed in
+    pub model_uuid: Option<String>,
+    /// The machine the unit is running on
+    pub machine_id: Option<String>,
+    /// The availability zone of the machine, if the cloud has them
+    pub availability_zone: Option<String>,
+    /// The directory the charm is unpacked in
+    pub charm_dir: Option<PathBuf>,
+    /// The version of the Juju agent running the hook
+    pub juju_version: Option<String>,
+    /// The storage instance of the current storage hook, ie: brick/0
+    pub storage_id: Option<String>,
+    /// The secret the current secret hook is about
+    pub secret_id: Option<SecretUri>,
+    /// The label of the secret the current secret hook is about, if it has one
+    pub secret_label: Option<String>,
+    /// The revision of the secret that expired or can be removed, in secret-expired and
+    /// secret-remove hooks
+    pub secret_revision: Option<u32>,
+    /// Proxy settings from the model config
+    pub proxy: ProxySettings,
+    relations_loaded: bool,
 }
 
 impl Context {
@@ -186,61 +454,153 @@ impl Context {
     /// extern crate juju;
     /// let context = juju::Context::new_from_env();
     /// ```
-
     pub fn new_from_env() -> Context {
-        let relations: HashMap<String, String> = HashMap::new();
-
-        // This variable is useless.  It only shows "server" for everything
-        let relation_type = env::var("JUJU_RELATION").unwrap_or("".to_string());
-        let relation_id_str = env::var("JUJU_RELATION_ID").unwrap_or("".to_string());
-        let parts: Vec<&str> = relation_id_str.split(":").collect();
-        let relation_id: usize;
-        if parts.len() > 1 {
-            relation_id = parts[1].parse::<usize>().unwrap_or(0);
-        } else {
-            relation_id = 0;
+        fn var(key: &str) -> Option<String> {
+            runner::var(key).ok().filter(|value| !value.is_empty())
+        }
+        fn parsed<T: FromStr>(key: &str) -> Option<T> {
+            var(key).and_then(|value| value.parse().ok())
         }
-        let unit = env::var("JUJU_UNIT_NAME").unwrap_or("".to_string());
 
+        let relation: Option<RelationId> = parsed("JUJU_RELATION_ID");
         Context {
-            relation_type: relation_type,
-            relation_id: relation_id,
-            unit: unit,
-            relations: relations,
+            relation_type: var("JUJU_RELATION").unwrap_or_default(),
+            relation_id: relation.as_ref().map(|id| id.id()).unwrap_or(0),
+            unit: var("JUJU_UNIT_NAME").unwrap_or_default(),
+            relations: HashMap::new(),
+            endpoint: var("JUJU_RELATION"),
+            relation,
+            unit_name: parsed("JUJU_UNIT_NAME"),
+            remote_unit: parsed("JUJU_REMOTE_UNIT"),
+            remote_app: parsed("JUJU_REMOTE_APP"),
+            departing_unit: parsed("JUJU_DEPARTING_UNIT"),
+            model_name: var("JUJU_MODEL_NAME"),
+            model_uuid: var("JUJU_MODEL_UUID"),
+            machine_id: var("JUJU_MACHINE_ID"),
+            availability_zone: var("JUJU_AVAILABILITY_ZONE"),
+            charm_dir: var("JUJU_CHARM_DIR").map(PathBuf::from),
+            juju_version: var("JUJU_VERSION"),
+            storage_id: var("JUJU_STORAGE_ID"),
+            secret_id: parsed("JUJU_SECRET_ID"),
+            secret_label: var("JUJU_SECRET_LABEL"),
+            secret_revision: parsed("JUJU_SECRET_REVISION"),
+            proxy: ProxySettings::from_env(),
+            relations_loaded: false,
+        }
+    }
+
+    /// Load the relation data the remote unit of the current relation hook has set into
+    /// `relations` and return it.  It is read with relation-get the first time this is called
+    /// and stays empty outside of relation hooks or when there is no remote unit, such as in
+    /// relation-broken.
+    /// # Failures
+    /// Returns a JujuError if relation-get fails or its output can not be parsed
+    pub fn load_relations(&mut self) -> Result<&HashMap<String, String>, JujuError> {
+        if !self.relations_loaded {
+            if let (Some(id), Some(unit)) = (&self.relation, &self.remote_unit) {
+                self.relations = relation_get_all_by_id(id, unit)?;
+            }
+            self.relations_loaded = true;
         }
+        Ok(&self.relations)
     }
 }
 
-#[derive(Debug)]
-pub struct Relation {
-    /// The name of a unit related to your service
+/// An action handler registered under the name of the action it handles.
+/// Build one with `Action::new` or the `action!` macro.
+pub struct Action {
+    /// The name of the action, as declared in actions.yaml
     pub name: String,
-    /// The id of the unit related to your service
-    pub id: usize,
+    /// Parses the parameters, calls the handler and records its results
+    callback: Box<dyn Fn() -> Result<(), String>>,
 }
 
-#[derive(Debug,PartialEq)]
-pub struct Hook {
-    /// The name of the hook to call
-    pub name: String,
-    /// A function to call when Juju calls this hook
-    /// # Failures
-    /// Your function passed in needs to return a String on error so that users will
-    /// know what happened.  Ideally this should also be logged with juju::log
-    pub callback: fn() -> Result<(), String>,
+impl Action {
+    /// Register `handler` for the action `name`.  The action parameters are deserialized into
+    /// `P` and whatever the handler returns is recorded with action-set.  Use
+    /// `serde_json::Value` for `P` to receive the parameters untyped, and return `()` when the
+    /// action has no results.
+    pub fn new<P, R, F>(name: &str, handler: F) -> Action
+        where P: DeserializeOwned,
+              R: Serialize,
+              F: Fn(P) -> Result<R, String> + 'static
+    {
+        Action {
+            name: name.to_string(),
+            callback: Box::new(move || {
+                let params: P = action_params()
+                    .map_err(|e| format!("Invalid action parameters: {}", e))?;
+                let results = handler(params)?;
+                set_action_results(&results).map_err(|e| e.to_string())
+            }),
+        }
+    }
 }
 
-/// Returns 0 if the process completed successfully.
-/// #Failures
-/// Returns a String of the stderr if the process failed to execute
-fn process_output(output: std::process::Output) -> Result<i32, JujuError> {
-    let status = output.status;
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Action").field("name", &self.name).finish()
+    }
+}
 
-    if status.success() {
-        return Ok(0);
-    } else {
-        return Err(JujuError::new(try!(String::from_utf8(output.stderr))));
+/// Record what an action handler returned.  Maps are set as (possibly nested) results, any
+/// other value is set under the `result` key and `()` sets nothing.
+fn set_action_results<R: Serialize>(results: &R) -> Result<(), JujuError> {
+    match serde_json::to_value(results)? {
+        serde_json::Value::Null => {}
+        results @ serde_json::Value::Object(_) => {
+            action_set_map(&results)?;
+        }
+        serde_json::Value::String(s) => {
+            action_set("result", &s)?;
+        }
+        other => {
+            action_set("result", &other.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a message to the juju debug-log.  If a level is given the message is logged at that
+/// level, otherwise Juju's default of INFO is used.
+pub fn log<T: fmt::Display>(message: T, level: Option<LogLevel>) {
+    let mut arg_list: Vec<String> = Vec::new();
+    if let Some(level) = level {
+        arg_list.push("-l".to_string());
+        arg_list.push(level.to_string());
+    }
+    arg_list.push(message.to_string());
+    // Logging is best effort.  There is nowhere left to report a failure to
+    let _ = run_tool("juju-log", &arg_list);
+}
+
+/// Run a hook tool and return what it printed to stdout, with surrounding whitespace trimmed.
+/// Every hook tool call goes through here so a failing tool is never mistaken for empty output.
+/// # Failures
+/// Returns JujuError::CommandFailed with the exit code and stderr if the tool did not succeed,
+/// or JujuError::ToolNotFound if it could not be found
+fn invoke(command: &str, arg_list: &[String], as_root: bool) -> Result<String, JujuError> {
+    let output = runner::run(command, arg_list, as_root)?;
+    if !output.succeeded() {
+        return Err(JujuError::CommandFailed {
+            tool: command.to_string(),
+            args: arg_list.to_vec(),
+            exit_code: output.exit_code,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
     }
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout.trim().to_string())
+}
+
+fn run_tool(command: &str, arg_list: &[String]) -> Result<String, JujuError> {
+    invoke(command, arg_list, false)
+}
+
+/// Run a hook tool that prints nothing useful.  Returns 0 if it succeeded.
+fn run_for_exit_code(command: &str, arg_list: &[String]) -> Result<i32, JujuError> {
+    run_tool(command, arg_list)?;
+    Ok(0)
 }
 
 /// This will reboot your juju instance.  Examples of using this are when a new kernel is installed
@@ -248,26 +608,53 @@ fn process_output(output: std::process::Output) -> Result<i32, JujuError> {
 /// # Failures
 /// Returns stderr if the reboot command fails
 pub fn reboot() -> Result<i32, JujuError> {
-    let output = try!(run_command_no_args("juju-reboot", true));
-    return process_output(output);
+    invoke("juju-reboot", &[], true)?;
+    Ok(0)
 }
 
-/// action_get_all gets all values that are set
+/// action_get_all gets all values that are set.  Nested parameters are returned with dotted
+/// keys, ie: `{"target": {"path": "/backups"}}` is returned as `target.path => /backups`
 /// See [Juju Actions](https://jujucharms.com/docs/devel/authors-charm-actions) for more information
 /// # Failures
 /// Returns stderr if the action_get command fails
 pub fn action_get_all() -> Result<HashMap<String, String>, JujuError> {
-    let output = try!(run_command_no_args("action-get", false));
-    let values = try!(String::from_utf8(output.stdout));
-    let mut map: HashMap<String, String> = HashMap::new();
+    let arg_list: Vec<String> = vec!["--format=json".to_string()];
+    let params: serde_json::Value = run_json("action-get", &arg_list)?;
 
-    for line in values.lines() {
-        let parts: Vec<&str> = line.split(":").collect();
-        if parts.len() == 2 {
-            map.insert(parts[0].to_string(), parts[1].trim().to_string());
-        }
-    }
-    return Ok(map);
+    let mut flattened: Vec<(String, String)> = Vec::new();
+    flatten_value("", &params, &mut flattened);
+    Ok(flattened.into_iter().collect())
+}
+
+/// Deserialize the parameters of the running action into a struct of your choosing
+/// # Examples
+/// ```
+/// extern crate juju;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// #[derive(Deserialize)]
+/// struct BackupParams {
+///     target: String,
+///     compress: bool,
+/// }
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     model.set_action_param("target", "/backups");
+///     model.set_action_param("compress", true);
+///     let _runner = model.install();
+///
+///     let params: BackupParams = juju::action_params().unwrap();
+///     assert_eq!(params.target, "/backups");
+///     assert!(params.compress);
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if action-get fails or the parameters do not match `T`
+pub fn action_params<T: DeserializeOwned>() -> Result<T, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string()];
+    run_json("action-get", &arg_list)
 }
 
 /// action_get gets the value of the parameter at the given key
@@ -275,36 +662,33 @@ pub fn action_get_all() -> Result<HashMap<String, String>, JujuError> {
 /// # Failures
 /// Returns stderr if the action_get command fails
 pub fn action_get(key: &str) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(key.to_string());
+    let arg_list: Vec<String> = vec![key.to_string()];
 
-    let output = try!(run_command("action-get", &arg_list, false));
-    let value = try!(String::from_utf8(output.stdout));
-    return Ok(value.trim().to_string());
+    run_tool("action-get", &arg_list)
 }
 
 /// Get the name of the currently executing action
 /// # Failures
 /// Returns JujuError if the environment variable JUJU_ACTION_NAME does not exist
 pub fn action_name() -> Result<String, JujuError> {
-    let name = try!(env::var("JUJU_ACTION_NAME"));
-    return Ok(name);
+    let name = runner::var("JUJU_ACTION_NAME")?;
+    Ok(name)
 }
 
 /// Get the uuid of the currently executing action
 /// # Failures
 /// Returns JujuError if the environment variable JUJU_ACTION_UUID does not exist
 pub fn action_uuid() -> Result<String, JujuError> {
-    let uuid = try!(env::var("JUJU_ACTION_UUID"));
-    return Ok(uuid);
+    let uuid = runner::var("JUJU_ACTION_UUID")?;
+    Ok(uuid)
 }
 
 /// Get the tag of the currently executing action
 /// # Failures
 /// Returns JujuError if the environment variable JUJU_ACTION_TAG does not exist
 pub fn action_tag() -> Result<String, JujuError> {
-    let tag = try!(env::var("JUJU_ACTION_TAG"));
-    return Ok(tag);
+    let tag = runner::var("JUJU_ACTION_TAG")?;
+    Ok(tag)
 }
 
 /// action_set permits the Action to set results in a map to be returned at completion of
@@ -312,13 +696,97 @@ pub fn action_tag() -> Result<String, JujuError> {
 /// See [Juju Actions](https://jujucharms.com/docs/devel/authors-charm-actions) for more
 /// information
 /// # Failures
-/// Returns stderr if the action_set command fails
+/// Returns a JujuError if `key` is not a valid action result key, see `action_set_map`, or
+/// the action_set command fails
 pub fn action_set(key: &str, value: &str) -> Result<i32, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(format!("{}={}", key, value));
+    check_action_key(key)?;
+    let arg_list: Vec<String> = vec![format!("{}={}", key, value)];
+
+    run_for_exit_code("action-set", &arg_list)
+}
+
+/// action_log sends a progress message to the operator while the action is still running.
+/// See [Juju Actions](https://jujucharms.com/docs/devel/authors-charm-actions) for more
+/// information
+/// # Failures
+/// Returns stderr if the action-log command fails
+pub fn action_log(msg: &str) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![msg.to_string()];
 
-    let output = try!(run_command("action-set", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("action-log", &arg_list)
+}
+
+/// action_set_map sets every result in `results` with a single call to action-set.  `results`
+/// must serialize to a map.  Nested maps are flattened into dotted keys which Juju turns back
+/// into nested results, ie: `{"bricks": {"brick1": {"size": 10}}}` is set as
+/// `bricks.brick1.size=10`.  Lists are set as their JSON representation.
+///
+/// Juju only accepts keys made of lowercase letters, digits and hyphens that start and end with
+/// a letter or digit, so serde field names like `saved_to` need renaming, ie: with
+/// `#[serde(rename_all = "kebab-case")]`.
+/// See [Juju Actions](https://jujucharms.com/docs/devel/authors-charm-actions) for more
+/// information
+/// # Failures
+/// Returns a JujuError if `results` is not a map, has a key Juju does not accept or the
+/// action-set command fails
+pub fn action_set_map<T: Serialize>(results: &T) -> Result<i32, JujuError> {
+    let results = serde_json::to_value(results)?;
+    if !results.is_object() {
+        return Err(JujuError::parse("Action results must be a map", &results.to_string()));
+    }
+    let mut flattened: Vec<(String, String)> = Vec::new();
+    flatten_value("", &results, &mut flattened);
+    if flattened.is_empty() {
+        return Ok(0);
+    }
+    for (key, _) in &flattened {
+        check_action_key(key)?;
+    }
+    let arg_list: Vec<String> = flattened.into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    run_for_exit_code("action-set", &arg_list)
+}
+
+/// Whether action-set accepts `key`: dot separated parts of lowercase letters, digits and
+/// hyphens that start and end with a letter or digit
+fn valid_action_key(key: &str) -> bool {
+    key.split('.').all(|part| {
+        let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+        part.starts_with(alphanumeric) && part.ends_with(alphanumeric) &&
+        part.chars().all(|c| alphanumeric(c) || c == '-')
+    })
+}
+
+fn check_action_key(key: &str) -> Result<(), JujuError> {
+    if valid_action_key(key) {
+        Ok(())
+    } else {
+        Err(JujuError::parse("Action result keys may only contain lowercase letters, digits and \
+                              hyphens",
+                             key))
+    }
+}
+
+/// Flatten nested JSON maps into dotted keys and string values the way action-get and
+/// action-set present them.  Nulls are skipped.
+fn flatten_value(prefix: &str, value: &serde_json::Value, out: &mut Vec<(String, String)>) {
+    match *value {
+        serde_json::Value::Object(ref map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_value(&key, value, out);
+            }
+        }
+        serde_json::Value::Null => {}
+        serde_json::Value::String(ref s) => out.push((prefix.to_string(), s.clone())),
+        ref other => out.push((prefix.to_string(), other.to_string())),
+    }
 }
 
 /// See [Juju Actions](https://jujucharms.com/docs/devel/authors-charm-actions) for more
@@ -326,335 +794,542 @@ pub fn action_set(key: &str, value: &str) -> Result<i32, JujuError> {
 /// # Failures
 /// Returns stderr if the action_fail command fails
 pub fn action_fail(msg: &str) -> Result<i32, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(msg.to_string());
+    let arg_list: Vec<String> = vec![msg.to_string()];
 
-    let output = try!(run_command("action-fail", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("action-fail", &arg_list)
 }
 
 /// This will return the private IP address associated with the unit.
 /// It can be very useful for services that require communicating with the other units related
-/// to it.
+/// to it.  unit-get is deprecated in Juju 2 and is not aware of network spaces, prefer
+/// network_get for the endpoint you are serving.
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn unit_get_private_addr() -> Result<IpAddr, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push("private-address".to_string());
+    let arg_list: Vec<String> = vec!["private-address".to_string()];
 
-    let output = try!(run_command("unit-get", &arg_list, false));
-    let private_addr: String = try!(String::from_utf8(output.stdout));
-    let ip = try!(IpAddr::from_str(private_addr.trim()));
-    return Ok(ip);
+    let private_addr = run_tool("unit-get", &arg_list)?;
+    let ip = IpAddr::from_str(&private_addr)?;
+    Ok(ip)
 }
 
 /// This will return the public IP address associated with the unit.
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn unit_get_public_addr() -> Result<IpAddr, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push("public-address".to_string());
+    let arg_list: Vec<String> = vec!["public-address".to_string()];
 
-    let output = try!(run_command("unit-get", &arg_list, false));
-    let public_addr = try!(String::from_utf8(output.stdout));
-    let ip = try!(IpAddr::from_str(public_addr.trim()));
-    return Ok(ip);
+    let public_addr = run_tool("unit-get", &arg_list)?;
+    let ip = IpAddr::from_str(&public_addr)?;
+    Ok(ip)
 }
 
 /// This will return a configuration item that corresponds to the key passed in
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn config_get(key: &str) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(key.to_string());
+    let arg_list: Vec<String> = vec![key.to_string()];
 
-    let output = try!(run_command("config-get", &arg_list, false));
-    let value = try!(String::from_utf8(output.stdout));
-    return Ok(value.trim().to_string());
+    run_tool("config-get", &arg_list)
 }
 
 /// config_get_all will return all configuration options as a HashMap<String,String>
+/// Values that are not strings, such as booleans and numbers, are converted to their JSON
+/// representation.  Options without a value are left out.
 /// # Failures
-/// Returns a String of if the configuration options are not able to be transformed into a HashMap
+/// Returns a JujuError if config-get fails or its output can not be parsed
 pub fn config_get_all() -> Result<HashMap<String, String>, JujuError> {
-    let mut values: HashMap<String, String> = HashMap::new();
-
-    let arg_list: Vec<String> = vec!["--all".to_string()];
-    let output = try!(run_command("config-get", &arg_list, false));
-    let output_str = try!(String::from_utf8(output.stdout));
-    //  Example output:
-    // "brick_paths: /mnt/brick1 /mnt/brick2\ncluster_type: Replicate\n"
-    //
-    // For each line split at : and load the parts into the HashMap
-    for line in output_str.lines() {
-        let parts: Vec<&str> = line.split(":").filter(|s| !s.is_empty()).collect::<Vec<&str>>();
-        if !parts.len() == 2 {
-            // Skipping this possibly bogus value
-            continue;
-        }
-        let key = match parts.get(0) {
-            Some(key) => key,
-            None => {
-                return Err(JujuError::new(format!("Unable to get key from config-get from \
-                                                   parts: {:?}",
-                                                  parts)));
-            }
-        };
-        let value = match parts.get(1) {
-            Some(value) => value,
-            None => {
-                return Err(JujuError::new(format!("Unable to get value from config-get from \
-                                                   parts: {:?}",
-                                                  parts)));
-            }
-        };
-        values.insert(key.to_string(), value.to_string());
-    }
+    let values = config_get_values()?;
+    Ok(values.into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some((key, s)),
+            other => Some((key, other.to_string())),
+        })
+        .collect())
+}
+
+/// config_get_values will return all configuration options with the types Juju gave them.
+/// Options without a value are returned as `Value::Null`
+/// # Failures
+/// Returns a JujuError if config-get fails or its output can not be parsed
+pub fn config_get_values() -> Result<HashMap<String, serde_json::Value>, JujuError> {
+    let arg_list: Vec<String> = vec!["--all".to_string(), "--format=json".to_string()];
+    run_json("config-get", &arg_list)
+}
 
-    return Ok(values);
+/// Deserialize the whole charm configuration into a struct of your choosing
+/// # Examples
+/// ```
+/// extern crate juju;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     brick_paths: String,
+///     replication_level: u32,
+///     sysctl: Option<String>,
+/// }
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     model.set_config("brick_paths", "/mnt/brick1 /mnt/brick2");
+///     model.set_config("replication_level", 3);
+///     let _runner = model.install();
+///
+///     let config: Config = juju::config().unwrap();
+///     assert_eq!(config.replication_level, 3);
+///     assert!(config.sysctl.is_none());
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if config-get fails or the configuration does not match `T`
+pub fn config<T: DeserializeOwned>() -> Result<T, JujuError> {
+    let arg_list: Vec<String> = vec!["--all".to_string(), "--format=json".to_string()];
+    run_json("config-get", &arg_list)
 }
 
 /// This will expose a port on the unit.  The transport argument will indicate whether tcp or udp
-/// should be exposed
+/// should be exposed.  See open_port_on for port ranges, ICMP and opening a port for only some
+/// endpoints, and set_ports for keeping the open ports in line with the config.
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn open_port(port: usize, transport: Transport) -> Result<i32, JujuError> {
     let mut arg_list: Vec<String> = Vec::new();
-    let port_string = format!("{}/{}", port.to_string(), transport.to_string());
+    let port_string = format!("{}/{}", port, transport);
 
     arg_list.push(port_string);
-    let output = try!(run_command("open-port", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("open-port", &arg_list)
 }
 
 /// This will hide a port on the unit.  The transport argument will indicate whether tcp or udp
 /// should be exposed
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn close_port(port: usize, transport: Transport) -> Result<i32, JujuError> {
     let mut arg_list: Vec<String> = Vec::new();
-    let port_string = format!("{}/{}", port.to_string(), transport.to_string());
+    let port_string = format!("{}/{}", port, transport);
 
     arg_list.push(port_string);
-    let output = try!(run_command("close-port", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("close-port", &arg_list)
 }
 
 /// Set relation information for the current unit
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn relation_set(key: &str, value: &str) -> Result<i32, JujuError> {
     let mut arg_list: Vec<String> = Vec::new();
-    let arg = format!("{}={}", key.clone(), value);
+    let arg = format!("{}={}", key, value);
 
     arg_list.push(arg);
-    let output = try!(run_command("relation-set", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("relation-set", &arg_list)
 }
 /// Sets relation information using a specific relation ID. Used outside of relation hooks
 /// # Failures
-/// Will return a String of the stderr if the call fails
-pub fn relation_set_by_id(key: &str, value: &str, id: &Relation) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-
-    arg_list.push(format!("-r {}:{}", id.name, id.id.to_string()));
-    arg_list.push(format!("{}={}", key, value).to_string());
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_set_by_id(key: &str, value: &str, id: &RelationId) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec!["-r".to_string(),
+                                     id.to_string(),
+                                     format!("{}={}", key, value)];
 
-    let output = try!(run_command("relation-set", &arg_list, false));
-    let relation = try!(String::from_utf8(output.stdout));
-    return Ok(relation);
+    run_for_exit_code("relation-set", &arg_list)
 }
 
 /// Get relation information for the current unit
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn relation_get(key: &str) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(key.to_string());
-    let output = try!(run_command("relation-get", &arg_list, false));
-    let value = try!(String::from_utf8(output.stdout));
-    return Ok(value);
+    let arg_list: Vec<String> = vec![key.to_string()];
+    run_tool("relation-get", &arg_list)
 }
 
 /// Get relation information for a specific unit
 /// # Failures
-/// Will return a String of the stderr if the call fails
-pub fn relation_get_by_unit(key: &str, unit: &Relation) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(key.to_string());
-    arg_list.push(format!("{}/{}", unit.name, unit.id.to_string()));
-
-    let output = try!(run_command("relation-get", &arg_list, false));
-    let relation = try!(String::from_utf8(output.stdout));
-    return Ok(relation);
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_get_by_unit(key: &str, unit: &UnitName) -> Result<String, JujuError> {
+    let arg_list: Vec<String> = vec![key.to_string(), unit.to_string()];
+    run_tool("relation-get", &arg_list)
 }
 
 /// Get relation information using a specific relation ID. Used outside of relation hooks
 /// # Failures
-/// Will return a String of the stderr if the call fails
-pub fn relation_get_by_id(key: &str, id: &Relation, unit: &Relation) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_get_by_id(key: &str,
+                          id: &RelationId,
+                          unit: &UnitName)
+                          -> Result<String, JujuError> {
+    let arg_list: Vec<String> = vec!["-r".to_string(),
+                                     id.to_string(),
+                                     key.to_string(),
+                                     unit.to_string()];
+    run_tool("relation-get", &arg_list)
+}
 
-    arg_list.push(format!("-r {}:{}", id.name, id.id.to_string()));
-    arg_list.push(format!("{}", key.to_string()));
-    arg_list.push(format!("{}/{}", unit.name, unit.id.to_string()));
+/// Get the whole relation data bag of the remote unit that triggered the current relation hook
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     let id = model.add_relation("server", "gluster");
+///     model.update_relation_data(&id, "gluster/1", &[("hostname", "gluster-1"), ("brick", "/a")]);
+///     let _runner = model.install();
+///     model.set_env("JUJU_RELATION_ID", &id);
+///     model.set_env("JUJU_REMOTE_UNIT", "gluster/1");
+///
+///     let settings = juju::relation_get_all().unwrap();
+///     assert_eq!(settings["hostname"], "gluster-1");
+///     assert_eq!(settings["brick"], "/a");
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if relation-get fails or its output can not be parsed
+pub fn relation_get_all() -> Result<HashMap<String, String>, JujuError> {
+    relation_get_map(None, false, None)
+}
 
-    let output = try!(run_command("relation-get", &arg_list, false));
-    let relation = try!(String::from_utf8(output.stdout));
-    return Ok(relation);
+/// Get the whole relation data bag of `unit` on the relation of the current relation hook
+/// # Failures
+/// Returns a JujuError if relation-get fails or its output can not be parsed
+pub fn relation_get_all_by_unit(unit: &UnitName) -> Result<HashMap<String, String>, JujuError> {
+    relation_get_map(None, false, Some(unit.to_string()))
 }
 
-/// Returns a list of all related units
+/// Get the whole relation data bag of `unit` on the relation `id`.  Used outside of relation
+/// hooks
 /// # Failures
-/// Will return a String of the stderr if the call fails
-pub fn relation_list() -> Result<Vec<Relation>, JujuError> {
-    let mut related_units: Vec<Relation> = Vec::new();
+/// Returns a JujuError if relation-get fails or its output can not be parsed
+pub fn relation_get_all_by_id(id: &RelationId,
+                              unit: &UnitName)
+                              -> Result<HashMap<String, String>, JujuError> {
+    relation_get_map(Some(id), false, Some(unit.to_string()))
+}
 
-    let output = try!(run_command_no_args("relation-list", false));
-    let output_str = try!(String::from_utf8(output.stdout));
+/// Get the application data bag of the remote application in the current relation hook.
+/// Application data is shared by every unit of an application and is where most interfaces
+/// exchange credentials and endpoints.
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("wordpress/0");
+///     let id = model.add_relation("db", "mysql");
+///     model.update_app_relation_data(&id, "mysql", &[("user", "wp"), ("password", "s3cret")]);
+///     let _runner = model.install();
+///     model.set_env("JUJU_RELATION_ID", &id);
+///     model.set_env("JUJU_REMOTE_APP", "mysql");
+///
+///     let credentials = juju::relation_get_app().unwrap();
+///     assert_eq!(credentials["user"], "wp");
+///     assert_eq!(credentials["password"], "s3cret");
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if JUJU_REMOTE_APP is not set, relation-get fails or its output can not
+/// be parsed
+pub fn relation_get_app() -> Result<HashMap<String, String>, JujuError> {
+    let app: ApplicationName = runner::var("JUJU_REMOTE_APP")?.parse()?;
+    relation_get_map(None, true, Some(app.to_string()))
+}
 
-    log(&format!("relation-list output: {}", output_str),
-        Some(LogLevel::Debug));
+/// Get the application data bag of `app` on the relation `id`.  `app` can be the remote
+/// application or, on the leader, the local one.  Used outside of relation hooks
+/// # Failures
+/// Returns a JujuError if relation-get fails or its output can not be parsed
+pub fn relation_get_app_by_id(id: &RelationId,
+                              app: &ApplicationName)
+                              -> Result<HashMap<String, String>, JujuError> {
+    relation_get_map(Some(id), true, Some(app.to_string()))
+}
 
-    for line in output_str.lines() {
-        let v: Vec<&str> = line.split('/').collect();
-        let id: usize = try!(v[1].parse::<usize>());
-        let r: Relation = Relation {
-            name: v[0].to_string(),
-            id: id,
-        };
-        related_units.push(r);
+/// Read a whole data bag.  `target` is the unit, or with `app` the application, to read from
+fn relation_get_map(id: Option<&RelationId>,
+                    app: bool,
+                    target: Option<String>)
+                    -> Result<HashMap<String, String>, JujuError> {
+    let mut arg_list: Vec<String> = vec!["--format=json".to_string()];
+    if let Some(id) = id {
+        arg_list.push("-r".to_string());
+        arg_list.push(id.to_string());
     }
-    return Ok(related_units);
+    if app {
+        arg_list.push("--app".to_string());
+    }
+    // - asks for every key instead of a single one
+    arg_list.push("-".to_string());
+    arg_list.extend(target);
+    // A unit that has not written anything yet has a null data bag
+    let settings: Option<HashMap<String, String>> = run_json("relation-get", &arg_list)?;
+    Ok(settings.unwrap_or_default())
 }
 
-/// Returns a list of all related units for the supplied identifier
+/// Values longer than this, or containing a newline, are passed to relation-set in a file
+/// rather than on the command line
+const RELATION_SET_MAX_ARG: usize = 4096;
+
+/// Set several keys in the current unit's relation data bag with a single relation-set call.
+/// Setting a key to an empty value removes it.  Large or multi-line values are written to a
+/// temporary file and handed over with `--file`.
 /// # Failures
-/// Will return a String of the stderr if the call fails
-pub fn relation_list_by_id(id: &Relation) -> Result<Vec<Relation>, JujuError> {
-    let mut related_units: Vec<Relation> = Vec::new();
-    let mut arg_list: Vec<String> = Vec::new();
+/// Returns a JujuError if the file can not be written or relation-set fails
+pub fn relation_set_many(settings: &[(&str, &str)]) -> Result<i32, JujuError> {
+    relation_set_settings(Vec::new(), settings)
+}
 
-    arg_list.push(format!("-r {}:{}", id.name, id.id.to_string()));
+/// Set several keys in the current unit's relation data bag on the relation `id`.  Used
+/// outside of relation hooks
+/// # Failures
+/// Returns a JujuError if the file can not be written or relation-set fails
+pub fn relation_set_many_by_id(settings: &[(&str, &str)],
+                               id: &RelationId)
+                               -> Result<i32, JujuError> {
+    relation_set_settings(vec!["-r".to_string(), id.to_string()], settings)
+}
 
-    let output = try!(run_command("relation-list", &arg_list, false));
-    let output_str = try!(String::from_utf8(output.stdout));
+/// Set several keys in the local application's data bag on the relation of the current
+/// relation hook.  Only the leader may write application data.
+/// # Failures
+/// Returns JujuError::NotLeader if this unit is not the leader, or stderr if relation-set fails
+pub fn relation_set_app(settings: &[(&str, &str)]) -> Result<i32, JujuError> {
+    if !is_leader()? {
+        return Err(JujuError::NotLeader);
+    }
+    relation_set_settings(vec!["--app".to_string()], settings)
+}
 
-    log(&format!("relation-list output: {}", output_str),
-        Some(LogLevel::Debug));
+/// Set several keys in the local application's data bag on the relation `id`.  Used outside
+/// of relation hooks.  Only the leader may write application data.
+/// # Failures
+/// Returns JujuError::NotLeader if this unit is not the leader, or stderr if relation-set fails
+pub fn relation_set_app_by_id(settings: &[(&str, &str)],
+                              id: &RelationId)
+                              -> Result<i32, JujuError> {
+    if !is_leader()? {
+        return Err(JujuError::NotLeader);
+    }
+    relation_set_settings(vec!["-r".to_string(), id.to_string(), "--app".to_string()],
+                          settings)
+}
 
-    for line in output_str.lines() {
-        let v: Vec<&str> = line.split('/').collect();
-        let id: usize = try!(v[1].parse::<usize>());
-        let r: Relation = Relation {
-            name: v[0].to_string(),
-            id: id,
-        };
-        related_units.push(r);
+fn relation_set_settings(mut arg_list: Vec<String>,
+                         settings: &[(&str, &str)])
+                         -> Result<i32, JujuError> {
+    let use_file = settings.iter()
+        .any(|&(_, value)| value.contains('\n') || value.len() > RELATION_SET_MAX_ARG);
+    if !use_file {
+        arg_list.extend(settings.iter().map(|&(key, value)| format!("{}={}", key, value)));
+        return run_for_exit_code("relation-set", &arg_list);
     }
-    return Ok(related_units);
+    run_with_settings_file("relation-set", arg_list, settings)?;
+    Ok(0)
 }
 
-pub fn relation_ids() -> Result<Vec<Relation>, JujuError> {
-    let mut related_units: Vec<Relation> = Vec::new();
-    let output = try!(run_command_no_args("relation-ids", false));
-    let output_str: String = try!(String::from_utf8(output.stdout));
-    log(&format!("relation-ids output: {}", output_str),
+/// Run a hook tool with `settings` written to a YAML file passed as `--file`.  The file is only
+/// readable by the current user, so it is also fit for secret content.
+fn run_with_settings_file(command: &str,
+                          mut arg_list: Vec<String>,
+                          settings: &[(&str, &str)])
+                          -> Result<String, JujuError> {
+    static FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let file_settings: BTreeMap<&str, &str> = settings.iter().cloned().collect();
+    let path = env::temp_dir().join(format!("juju-{}-{}-{}.yaml",
+                                            command,
+                                            process::id(),
+                                            FILE_COUNT.fetch_add(1, Ordering::SeqCst)));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&path)?.write_all(serde_yaml::to_string(&file_settings)?.as_bytes())?;
+    arg_list.push("--file".to_string());
+    arg_list.push(path.to_string_lossy().into_owned());
+    let result = run_tool(command, &arg_list);
+    // Failing to remove the file is not worth failing the tool call over
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Returns a list of all related units
+/// # Failures
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_list() -> Result<Vec<UnitName>, JujuError> {
+    let output_str = run_tool("relation-list", &[])?;
+
+    log(format!("relation-list output: {}", output_str),
         Some(LogLevel::Debug));
 
-    for line in output_str.lines() {
-        let v: Vec<&str> = line.split(':').collect();
-        let id: usize = try!(v[1].parse::<usize>());
-        let r: Relation = Relation {
-            name: v[0].to_string(),
-            id: id,
-        };
-        related_units.push(r);
-    }
-    return Ok(related_units);
+    output_str.lines().map(|line| line.parse()).collect()
 }
 
-/// Gets the relation IDs by their identifier
+/// Returns a list of all related units for the supplied identifier
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_list_by_id(id: &RelationId) -> Result<Vec<UnitName>, JujuError> {
+    let arg_list: Vec<String> = vec!["-r".to_string(), id.to_string()];
 
-pub fn relation_ids_by_identifier(id: &str) -> Result<Vec<Relation>, JujuError> {
-    let mut related_units: Vec<Relation> = Vec::new();
-    let mut arg_list: Vec<String> = Vec::new();
+    let output_str = run_tool("relation-list", &arg_list)?;
+
+    log(format!("relation-list output: {}", output_str),
+        Some(LogLevel::Debug));
 
-    arg_list.push(id.to_string());
+    output_str.lines().map(|line| line.parse()).collect()
+}
 
-    let output = try!(run_command("relation-ids", &arg_list, false));
-    let output_str: String = try!(String::from_utf8(output.stdout));
-    log(&format!("relation-ids output: {}", output_str),
+/// Returns the ids of every relation on the endpoint of the current relation hook
+/// # Failures
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_ids() -> Result<Vec<RelationId>, JujuError> {
+    let output_str = run_tool("relation-ids", &[])?;
+    log(format!("relation-ids output: {}", output_str),
         Some(LogLevel::Debug));
 
-    for line in output_str.lines() {
-        let v: Vec<&str> = line.split(':').collect();
-        let id: usize = try!(v[1].parse::<usize>());
-        let r: Relation = Relation {
-            name: v[0].to_string(),
-            id: id,
-        };
-        related_units.push(r);
+    output_str.lines().map(|line| line.parse()).collect()
+}
+
+/// Gets the relation IDs of every relation on `endpoint`, ie: the relation name from
+/// metadata.yaml
+/// # Failures
+/// Returns JujuError::CommandFailed with the stderr if the call fails
+pub fn relation_ids_by_identifier(endpoint: &str) -> Result<Vec<RelationId>, JujuError> {
+    let arg_list: Vec<String> = vec![endpoint.to_string()];
+
+    let output_str = run_tool("relation-ids", &arg_list)?;
+    log(format!("relation-ids output: {}", output_str),
+        Some(LogLevel::Debug));
+
+    output_str.lines().map(|line| line.parse()).collect()
+}
+
+/// Charms can only set maintenance, waiting, active and blocked.  The other states are Juju's.
+fn check_settable(status_type: StatusType) -> Result<(), JujuError> {
+    match status_type {
+        StatusType::Maintenance | StatusType::Waiting | StatusType::Active |
+        StatusType::Blocked => Ok(()),
+        StatusType::Error | StatusType::Unknown | StatusType::Terminated => {
+            Err(JujuError::parse("Only Juju can set this status", &status_type.to_string()))
+        }
     }
-    return Ok(related_units);
 }
 
 /// Set the status of your unit to indicate to the Juju if everything is ok or something is wrong.
 /// See the Status enum for information about what can be set.
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::Parse without running status-set if the status is one only Juju can set,
+/// ie: error, unknown or terminated.  Returns JujuError::CommandFailed with the stderr if the
+/// call fails
 pub fn status_set(status: Status) -> Result<i32, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push(status.status_type.to_string());
-    arg_list.push(status.message);
+    check_settable(status.status_type)?;
+    let arg_list: Vec<String> = vec![status.status_type.to_string(),
+                                     status.message];
 
-    let output = try!(run_command("status-set", &arg_list, false));
-    return process_output(output);
+    run_for_exit_code("status-set", &arg_list)
+}
+
+/// Set the status of the whole application.  Juju shows it in place of the unit statuses in
+/// the application column of `juju status`.
+/// # Failures
+/// Returns JujuError::Parse if the status is one only Juju can set, JujuError::NotLeader if this
+/// unit is not the leader, or JujuError::CommandFailed if status-set fails
+pub fn status_set_application(status: Status) -> Result<i32, JujuError> {
+    check_settable(status.status_type)?;
+    if !is_leader()? {
+        return Err(JujuError::NotLeader);
+    }
+    let arg_list: Vec<String> = vec!["--application".to_string(),
+                                     status.status_type.to_string(),
+                                     status.message];
+
+    run_for_exit_code("status-set", &arg_list)
 }
 
 /// Retrieve the previously set juju workload state
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn status_get() -> Result<String, JujuError> {
-    let output = try!(run_command_no_args("status-get", false));
-    return Ok(try!(String::from_utf8(output.stdout)));
+    run_tool("status-get", &[])
+}
+
+/// Retrieve the previously set juju workload state of this unit along with its message and
+/// status data
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     let _runner = model.install();
+///
+///     juju::status_set(juju::Status {
+///             status_type: juju::StatusType::Waiting,
+///             message: "waiting for peers".to_string(),
+///         })
+///         .unwrap();
+///     let status = juju::status_get_info().unwrap().status;
+///     assert_eq!(status.status_type, juju::StatusType::Waiting);
+///     assert_eq!(status.message, "waiting for peers");
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if status-get fails or its output can not be parsed
+pub fn status_get_info() -> Result<StatusInfo, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string(), "--include-data".to_string()];
+    let status: RawStatus = run_json("status-get", &arg_list)?;
+    status.parse()
+}
+
+/// Retrieve the status of the whole application along with the status of each of its units
+/// # Failures
+/// Returns JujuError::NotLeader if this unit is not the leader, or a JujuError if status-get
+/// fails or its output can not be parsed
+pub fn status_get_application() -> Result<StatusInfo, JujuError> {
+    if !is_leader()? {
+        return Err(JujuError::NotLeader);
+    }
+    let arg_list: Vec<String> = vec!["--format=json".to_string(),
+                                     "--include-data".to_string(),
+                                     "--application".to_string()];
+    let mut status: HashMap<String, RawStatus> = run_json("status-get", &arg_list)?;
+    match status.remove("application-status") {
+        Some(status) => status.parse(),
+        None => {
+            Err(JujuError::parse("Expected application-status in status-get output",
+                                 &format!("{:?}", status.keys().collect::<Vec<_>>())))
+        }
+    }
 }
 
 /// If storage drives were allocated to your unit this will get the path of them.
 /// In the storage-attaching hook this will tell you the location where the storage
 /// is attached to.  IE: /dev/xvdf for block devices or /mnt/{name} for filesystem devices
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn storage_get_location() -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push("location".to_string());
-    let output = try!(run_command("storage-get", &arg_list, false));
-    return Ok(try!(String::from_utf8(output.stdout)));
+    let arg_list: Vec<String> = vec!["location".to_string()];
+    run_tool("storage-get", &arg_list)
 }
 
 /// Return the location of the mounted storage device.  The mounted
 /// storage devices can be gotten by calling storage_list() and
 /// then passed into this function to get their mount location.
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn storage_get(name: &str) -> Result<String, JujuError> {
-    let mut arg_list: Vec<String> = Vec::new();
-    arg_list.push("-s".to_string());
-    arg_list.push(name.to_string());
-    arg_list.push("location".to_string());
-    let output = try!(run_command("storage-get", &arg_list, false));
-    return Ok(try!(String::from_utf8(output.stdout)));
+    let arg_list: Vec<String> = vec!["-s".to_string(),
+                                     name.to_string(),
+                                     "location".to_string()];
+    run_tool("storage-get", &arg_list)
 }
 
 /// Used to list storage instances that are attached to the unit.
 /// The names returned may be passed through to storage_get
 /// # Failures
-/// Will return a String of the stderr if the call fails
+/// Returns JujuError::CommandFailed with the stderr if the call fails
 pub fn storage_list() -> Result<String, JujuError> {
-    let output = try!(run_command_no_args("storage-list", false));
-    return Ok(try!(String::from_utf8(output.stdout)));
+    run_tool("storage-list", &[])
 }
 
 /// Call this to process your cmd line arguments and call any needed hooks
@@ -684,18 +1359,226 @@ pub fn storage_list() -> Result<String, JujuError> {
 ///     }
 /// ```
 ///
-pub fn process_hooks(registry: Vec<Hook>) -> Result<(), String> {
-    let hook_name = match charmhelpers::core::hookenv::hook_name() {
-        Some(s) => s,
-        _ => "".to_string(),
+pub fn process_hooks<E, R>(registry: R) -> Result<(), String>
+    where E: fmt::Display,
+          R: Into<HookRegistry<E>>
+{
+    let event = HookEvent::current().map_err(|e| e.to_string())?;
+    let mut context = HookContext::new(event);
+    registry.into().run(&mut context)
+}
+
+/// Call this from your actions/<name> executables to run the matching action handler.
+/// If the handler returns an error, or the parameters can not be parsed, the action is
+/// marked as failed with action-fail and the error is returned.
+/// # Examples
+/// ```
+/// #[macro_use]
+/// extern crate juju;
+/// extern crate log;
+/// #[macro_use]
+/// extern crate serde_derive;
+///
+/// #[derive(Deserialize)]
+/// struct BackupParams {
+///     target: String,
+/// }
+///
+/// #[derive(Serialize)]
+/// #[serde(rename_all = "kebab-case")]
+/// struct BackupResults {
+///     saved_to: String,
+/// }
+///
+/// fn backup(params: BackupParams) -> Result<BackupResults, String> {
+///     Ok(BackupResults { saved_to: params.target })
+/// }
+///
+/// fn main() {
+///     let action_registry = vec![action!("backup", backup)];
+///     let result = juju::process_actions(action_registry);
+///
+///     if result.is_err() {
+///         juju::log(&format!("Action failed with error: {:?}", result.err()),
+///                   Some(log::LogLevel::Error));
+///     }
+/// }
+/// ```
+pub fn process_actions(registry: Vec<Action>) -> Result<(), String> {
+    let name = action_name()
+        .map_err(|e| format!("Unable to find the action name: {}", e))?;
+
+    for action in registry {
+        if action.name == name {
+            let result = (action.callback)();
+            if let Err(ref msg) = result {
+                action_fail(msg).map_err(|e| e.to_string())?;
+            }
+            return result;
+        }
+    }
+    let msg = format!("Warning: Unknown callback for action {}", name);
+    action_fail(&msg).map_err(|e| e.to_string())?;
+    Err(msg)
+}
+
+/// Run the matching action handler if Juju is running an action, otherwise run the matching
+/// hook.  This lets a single binary be linked into both the hooks and actions directories.
+pub fn dispatch<E, R>(hooks: R, actions: Vec<Action>) -> Result<(), String>
+    where E: fmt::Display,
+          R: Into<HookRegistry<E>>
+{
+    if runner::var("JUJU_ACTION_NAME").is_ok() {
+        process_actions(actions)
+    } else {
+        process_hooks(hooks)
+    }
+}
+
+/// The exit code when a hook or action returns an error
+pub const EXIT_FAILURE: i32 = 1;
+
+/// The exit code when a hook or action panics, the same code Rust uses for a panicking main
+pub const EXIT_PANIC: i32 = 101;
+
+thread_local! {
+    /// Set while catch_panics is running on this thread, holds the message of a caught panic
+    static CAUGHT_PANIC: RefCell<Option<Option<String>>> = const { RefCell::new(None) };
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Install a panic hook, once for the whole process, that records the panics of threads
+/// running catch_panics and hands every other panic to the hook that was installed before it.
+/// Installing it once means threads running catch_panics at the same time can not lose or
+/// restore the wrong hook.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let caught = CAUGHT_PANIC.with(|caught| {
+                let mut caught = caught.borrow_mut();
+                match *caught {
+                    Some(ref mut message) => {
+                        *message = Some(panic_message(info));
+                        true
+                    }
+                    None => false,
+                }
+            });
+            if !caught {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Describe a panic the way the default hook does, with its location and message
+fn panic_message(info: &panic::PanicHookInfo) -> String {
+    let payload = info.payload();
+    let text = match payload.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None => {
+            payload.downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "Box<dyn Any>".to_string())
+        }
     };
+    match info.location() {
+        Some(location) => {
+            format!("panicked at {}:{}:{}: {}",
+                    location.file(),
+                    location.line(),
+                    location.column(),
+                    text)
+        }
+        None => format!("panicked: {}", text),
+    }
+}
 
-    for hook in registry {
-        if hook_name.contains(&hook.name) {
-            return (hook.callback)();
+/// Run `f`, turning a panic into an error holding the panic message and location.  The
+/// default panic output is suppressed on this thread while `f` runs.  If the charm replaces
+/// the panic hook itself, panics are still caught but only described as "panicked".
+fn catch_panics<F: FnOnce() -> Result<(), String>>(f: F) -> Result<(), PanicOrError> {
+    install_panic_hook();
+    let outer = CAUGHT_PANIC.with(|caught| caught.borrow_mut().replace(None));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let message = CAUGHT_PANIC.with(|caught| caught.replace(outer)).and_then(|m| m);
+
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(PanicOrError::Error(e)),
+        Err(_) => Err(PanicOrError::Panic(message.unwrap_or_else(|| "panicked".to_string()))),
+    }
+}
+
+enum PanicOrError {
+    Panic(String),
+    Error(String),
+}
+
+/// Dispatch the hook or action like `dispatch`, but never unwind or return an error.  Errors
+/// and panics are logged with juju-log at ERROR and, if `failure_status` is given, shown as
+/// the workload status.  Returns the exit code the process should exit with: 0 on success,
+/// EXIT_FAILURE if a handler returned an error and EXIT_PANIC if it panicked.  Any non-zero
+/// code makes Juju retry the hook.
+pub fn run_charm<E, R>(hooks: R, actions: Vec<Action>, failure_status: Option<StatusType>) -> i32
+    where E: fmt::Display,
+          R: Into<HookRegistry<E>>
+{
+    let registry = hooks.into();
+    let (message, code) = match catch_panics(move || dispatch(registry, actions)) {
+        Ok(()) => return 0,
+        Err(PanicOrError::Error(e)) => (e, EXIT_FAILURE),
+        Err(PanicOrError::Panic(e)) => (e, EXIT_PANIC),
+    };
+    let name = runner::var("JUJU_ACTION_NAME")
+        .ok()
+        .or_else(|| HookEvent::current().ok().map(|event| event.to_string()))
+        .unwrap_or_else(|| "hook".to_string());
+    log(format!("{} failed: {}", name, message), Some(LogLevel::Error));
+    if let Some(status_type) = failure_status {
+        let status = Status {
+            status_type,
+            message: format!("{} failed: {}", name, message),
+        };
+        if let Err(e) = status_set(status) {
+            log(format!("Unable to set status: {}", e),
+                Some(LogLevel::Error));
         }
     }
-    return Err(format!("Warning: Unknown callback for hook {}", hook_name));
+    code
+}
+
+/// The entry point for a charm binary.  Runs the hook or action with `run_charm` and exits the
+/// process with its exit code.
+/// # Examples
+/// ```no_run
+/// #[macro_use]
+/// extern crate juju;
+///
+/// fn config_changed() -> Result<(), String> {
+///     Ok(())
+/// }
+///
+/// fn main() {
+///     juju::main(vec![hook!("config-changed", config_changed)], Vec::new());
+/// }
+/// ```
+pub fn main<E, R>(hooks: R, actions: Vec<Action>) -> !
+    where E: fmt::Display,
+          R: Into<HookRegistry<E>>
+{
+    process::exit(run_charm(hooks, actions, None))
+}
+
+/// Like `main`, but failures are also shown as `status_type` status, usually
+/// StatusType::Blocked
+pub fn main_with_status<E, R>(hooks: R, actions: Vec<Action>, status_type: StatusType) -> !
+    where E: fmt::Display,
+          R: Into<HookRegistry<E>>
+{
+    process::exit(run_charm(hooks, actions, Some(status_type)))
 }
 
 /// Returns true/false if this unit is the leader
@@ -720,45 +1603,771 @@ pub fn process_hooks(registry: Vec<Hook>) -> Result<(), String> {
 /// ```
 ///
 pub fn is_leader() -> Result<bool, JujuError> {
-    let output = try!(run_command_no_args("is-leader", false));
-    let output_str: String = try!(String::from_utf8(output.stdout));
-    match output_str.trim().as_ref() {
+    let output_str = run_tool("is-leader", &[])?;
+    match output_str.as_ref() {
         "True" => Ok(true),
         "False" => Ok(false),
-        _ => Ok(false),
+        _ => Err(JujuError::parse("Unexpected is-leader output", &output_str)),
     }
 }
 
-fn run_command_no_args(command: &str, as_root: bool) -> Result<std::process::Output, JujuError> {
-    if as_root {
-        let mut cmd = std::process::Command::new("sudo");
-        let output = try!(cmd.output());
-        return Ok(output);
-    } else {
-        let mut cmd = std::process::Command::new(command);
-        let output = try!(cmd.output());
-        return Ok(output);
+/// The name of the hook being run.  Juju sets JUJU_HOOK_NAME for `juju-run` and newer hook
+/// invocations, otherwise the hook name is the name the binary was called by.
+fn hook_name() -> Option<String> {
+    match runner::var("JUJU_HOOK_NAME") {
+        Ok(s) => Some(s),
+        Err(_) => env::args().next(),
+    }
+}
+
+/// Run a hook tool that was asked for `--format=json` output and deserialize what it printed
+fn run_json<T: DeserializeOwned>(command: &str, arg_list: &[String]) -> Result<T, JujuError> {
+    let output = run_tool(command, arg_list)?;
+    Ok(serde_json::from_str(&output)?)
+}
+
+/// Get a single leadership setting.  Returns None if the leader has not set it.
+/// Leadership settings are how the leader shares generated passwords, cluster ids and the like
+/// with the other units.  Every unit can read them.
+/// # Failures
+/// Returns a JujuError if leader-get fails or its output can not be parsed
+pub fn leader_get(key: &str) -> Result<Option<String>, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string(), key.to_string()];
+    run_json("leader-get", &arg_list)
+}
+
+/// Get every leadership setting
+/// # Failures
+/// Returns a JujuError if leader-get fails or its output can not be parsed
+pub fn leader_get_all() -> Result<HashMap<String, String>, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string()];
+    // Juju prints null rather than {} when nothing has been set yet
+    let settings: Option<HashMap<String, String>> = run_json("leader-get", &arg_list)?;
+    Ok(settings.unwrap_or_default())
+}
+
+/// Set several leadership settings at once.  Setting a key to an empty value removes it.
+/// Every unit is told about the change with a leader-settings-changed hook.
+/// # Failures
+/// Returns JujuError::NotLeader if this unit is not the leader, or stderr if leader-set fails
+pub fn leader_set(settings: &[(&str, &str)]) -> Result<i32, JujuError> {
+    if !is_leader()? {
+        return Err(JujuError::NotLeader);
     }
+    let arg_list: Vec<String> = settings.iter()
+        .map(|&(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    run_for_exit_code("leader-set", &arg_list)
 }
 
-fn run_command(command: &str,
-               arg_list: &Vec<String>,
-               as_root: bool)
-               -> Result<std::process::Output, JujuError> {
-    if as_root {
-        let mut cmd = std::process::Command::new("sudo");
-        cmd.arg(command);
-        for arg in arg_list {
-            cmd.arg(&arg);
+/// Get a leadership setting, having the leader generate and share it first if it is not set.
+/// This is meant for values the whole application must agree on, such as passwords.  Call it
+/// from leader-elected to generate the value and from leader-settings-changed to pick it up.
+/// Units that are not the leader get None until the leader has set the value.
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("mysql/0");
+///     model.set_leader(true);
+///     let _runner = model.install();
+///
+///     let password = juju::leader_get_or_set("root-password", || "s3cret".to_string())
+///         .unwrap();
+///     assert_eq!(password, Some("s3cret".to_string()));
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if leader-get or leader-set fail
+pub fn leader_get_or_set<F>(key: &str, generate: F) -> Result<Option<String>, JujuError>
+    where F: FnOnce() -> String
+{
+    if let Some(value) = leader_get(key)? {
+        return Ok(Some(value));
+    }
+    if !is_leader()? {
+        return Ok(None);
+    }
+    let value = generate();
+    leader_set(&[(key, &value)])?;
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use runner::RunnerGuard;
+    use testing::FakeModel;
+
+    /// A model for gluster/0, installed until the guard is dropped at the end of the test
+    fn model() -> (FakeModel, RunnerGuard) {
+        let model = FakeModel::new("gluster/0");
+        let runner = model.install();
+        (model, runner)
+    }
+
+    #[test]
+    fn process_hooks_matches_hook_names_exactly() {
+        fn changed() -> Result<(), String> {
+            Err("changed should not run".to_string())
         }
-        let output = try!(cmd.output());
-        return Ok(output);
-    } else {
-        let mut cmd = std::process::Command::new(command);
-        for arg in arg_list {
-            cmd.arg(&arg);
+        fn server_changed() -> Result<(), String> {
+            ::status_set(::Status {
+                    status_type: ::StatusType::Active,
+                    message: "server".to_string(),
+                })
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        }
+
+        let (model, _runner) = model();
+        let registry = || {
+            vec![hook!("changed", changed),
+                 hook!("client-relation-changed", changed),
+                 hook!("server-relation-changed", server_changed)]
+        };
+        model.run_hook("hooks/server-relation-changed", registry()).unwrap();
+        assert_eq!(model.status(), Some(("active".to_string(), "server".to_string())));
+        assert!(model.run_hook("other-relation-changed", registry()).is_err());
+    }
+
+    #[test]
+    fn hook_closures_capture_state_and_receive_the_context() {
+        use std::cell::RefCell;
+        use std::fmt;
+        use std::rc::Rc;
+
+        #[derive(Debug)]
+        struct CharmError(String);
+        impl fmt::Display for CharmError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "charm error: {}", self.0)
+            }
+        }
+        impl From<::JujuError> for CharmError {
+            fn from(err: ::JujuError) -> CharmError {
+                CharmError(err.to_string())
+            }
+        }
+
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.update_relation_data(&id, "gluster/1", &[("hostname", "gluster-1")]);
+        let peers = Rc::new(RefCell::new(Vec::new()));
+        let seen = peers.clone();
+        let registry = vec![::HookHandler::new("server-relation-changed",
+                                         move |context: &mut ::HookContext| {
+            let hostname = context.load_relations()?["hostname"].clone();
+            seen.borrow_mut().push((context.event.endpoint().map(String::from), hostname));
+            ::relation_set("peer", "gluster-0")?;
+            Ok::<(), CharmError>(())
+        })];
+
+        model.run_relation_hook("server-relation-changed", &id, "gluster/1", registry).unwrap();
+        assert_eq!(*peers.borrow(),
+                   vec![(Some("server".to_string()), "gluster-1".to_string())]);
+
+        let registry = vec![::HookHandler::new("config-changed", |_: &mut ::HookContext| {
+            Err(CharmError("no bricks".to_string()))
+        })];
+        assert_eq!(model.run_hook("config-changed", registry),
+                   Err("charm error: no bricks".to_string()));
+    }
+
+    #[test]
+    fn run_charm_reports_errors_and_panics() {
+        fn config_changed() -> Result<(), String> {
+            Err("no bricks".to_string())
+        }
+        fn install() -> Result<(), String> {
+            panic!("no bricks to install on");
+        }
+        fn start() -> Result<(), String> {
+            Ok(())
+        }
+        let registry = || {
+            vec![hook!("config-changed", config_changed),
+                 hook!("install", install),
+                 hook!("start", start)]
+        };
+
+        let (model, _runner) = model();
+        model.set_env("JUJU_HOOK_NAME", "start");
+        assert_eq!(::run_charm(registry(), Vec::new(), None), 0);
+        assert!(model.logs().is_empty());
+
+        model.set_env("JUJU_HOOK_NAME", "config-changed");
+        assert_eq!(::run_charm(registry(), Vec::new(), None), ::EXIT_FAILURE);
+        assert_eq!(model.logs(),
+                   vec![(Some("ERROR".to_string()),
+                         "config-changed failed: no bricks".to_string())]);
+        assert_eq!(model.status(), None);
+
+        model.set_env("JUJU_HOOK_NAME", "install");
+        let code = ::run_charm(registry(), Vec::new(), Some(::StatusType::Blocked));
+        assert_eq!(code, ::EXIT_PANIC);
+        let (level, message) = model.logs().pop().unwrap();
+        assert_eq!(level, Some("ERROR".to_string()));
+        assert!(message.starts_with("install failed: panicked at src/lib.rs:"), "{}", message);
+        assert!(message.ends_with(": no bricks to install on"), "{}", message);
+        let (status, status_message) = model.status().unwrap();
+        assert_eq!(status, "blocked");
+        assert_eq!(status_message, message);
+    }
+
+    #[test]
+    fn panics_are_caught_on_several_threads_at_once() {
+        let threads: Vec<_> = (0..8)
+            .map(|n| {
+                ::std::thread::spawn(move || match ::catch_panics(|| panic!("thread {}", n)) {
+                    Err(::PanicOrError::Panic(message)) => {
+                        message.ends_with(&format!(": thread {}", n))
+                    }
+                    _ => false,
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn context_reads_the_hook_environment() {
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.update_relation_data(&id, "gluster/1", &[("hostname", "gluster-1")]);
+        model.set_env("JUJU_RELATION", "server");
+        model.set_env("JUJU_RELATION_ID", &id);
+        model.set_env("JUJU_REMOTE_UNIT", "gluster/1");
+        model.set_env("JUJU_REMOTE_APP", "gluster");
+        model.set_env("JUJU_MODEL_NAME", "storage");
+        model.set_env("JUJU_CHARM_DIR", "/var/lib/juju/agents/unit-gluster-0/charm");
+        model.set_env("JUJU_CHARM_HTTP_PROXY", "http://squid:3128");
+        model.set_env("https_proxy", "http://squid:3129");
+        model.set_env("JUJU_AVAILABILITY_ZONE", "");
+
+        let mut context = ::Context::new_from_env();
+        assert_eq!(context.relation_type, "server");
+        assert_eq!(context.relation_id, id.parse::<::RelationId>().unwrap().id());
+        assert_eq!(context.unit, "gluster/0");
+        assert!(context.relations.is_empty());
+        assert_eq!(context.endpoint, Some("server".to_string()));
+        assert_eq!(context.relation, Some(id.parse().unwrap()));
+        assert_eq!(context.unit_name, Some("gluster/0".parse().unwrap()));
+        assert_eq!(context.remote_unit, Some("gluster/1".parse().unwrap()));
+        assert_eq!(context.remote_app, Some("gluster".parse().unwrap()));
+        assert_eq!(context.departing_unit, None);
+        assert_eq!(context.model_name, Some("storage".to_string()));
+        assert_eq!(context.availability_zone, None);
+        assert_eq!(context.charm_dir,
+                   Some("/var/lib/juju/agents/unit-gluster-0/charm".into()));
+        assert_eq!(context.proxy.http_proxy, Some("http://squid:3128".to_string()));
+        assert_eq!(context.proxy.https_proxy, Some("http://squid:3129".to_string()));
+        assert_eq!(context.proxy.no_proxy, None);
+
+        model.update_relation_data(&id, "gluster/1", &[("brick", "/mnt/brick1")]);
+        assert_eq!(context.load_relations().unwrap()["brick"], "/mnt/brick1");
+        // Loaded once, so later changes are not picked up
+        model.update_relation_data(&id, "gluster/1", &[("brick", "")]);
+        assert_eq!(context.load_relations().unwrap()["hostname"], "gluster-1");
+        assert_eq!(context.relations.len(), 2);
+
+        model.set_env("JUJU_RELATION", "");
+        model.set_env("JUJU_RELATION_ID", "");
+        let mut outside = ::Context::new_from_env();
+        assert_eq!((outside.relation_type.as_ref(), outside.relation_id), ("", 0));
+        assert_eq!(outside.relation, None);
+        assert!(outside.load_relations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn config_get_all_keeps_values_containing_colons() {
+        let (model, _runner) = model();
+        model.set_config("endpoint", "http://[fe80::1]:8080/api");
+        model.set_config("start_time", "12:30:00");
+        model.set_config("replication_level", 3);
+        model.set_config("sysctl", ::serde_json::Value::Null);
+
+        let values = ::config_get_all().unwrap();
+        assert_eq!(values["endpoint"], "http://[fe80::1]:8080/api");
+        assert_eq!(values["start_time"], "12:30:00");
+        assert_eq!(values["replication_level"], "3");
+        assert!(!values.contains_key("sysctl"));
+    }
+
+    #[test]
+    fn config_deserializes_into_typed_structs() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config {
+            brick_paths: String,
+            replication_level: u32,
+            ssl: bool,
+            timeout: f64,
+        }
+
+        let (model, _runner) = model();
+        model.set_config("brick_paths", "/mnt/brick1 /mnt/brick2");
+        model.set_config("replication_level", 3);
+        model.set_config("ssl", true);
+        model.set_config("timeout", 1.5);
+
+        let config: Config = ::config().unwrap();
+        assert_eq!(config,
+                   Config {
+                       brick_paths: "/mnt/brick1 /mnt/brick2".to_string(),
+                       replication_level: 3,
+                       ssl: true,
+                       timeout: 1.5,
+                   });
+    }
+
+    #[test]
+    fn action_get_all_flattens_nested_params() {
+        let (model, _runner) = model();
+        model.set_action_param("target.path", "/backups");
+        model.set_action_param("target.host", "10.0.0.1:22");
+        model.set_action_param("retries", 3);
+
+        let params = ::action_get_all().unwrap();
+        assert_eq!(params["target.path"], "/backups");
+        assert_eq!(params["target.host"], "10.0.0.1:22");
+        assert_eq!(params["retries"], "3");
+    }
+
+    #[test]
+    fn action_set_map_sets_nested_results_in_one_call() {
+        #[derive(Serialize)]
+        struct BrickResult {
+            size: u64,
+            seconds: f64,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct BackupResult {
+            bricks: ::std::collections::BTreeMap<String, BrickResult>,
+            backup_outcome: String,
+        }
+
+        let (model, _runner) = model();
+        let mut bricks = ::std::collections::BTreeMap::new();
+        bricks.insert("brick-1".to_string(),
+                      BrickResult {
+                          size: 1024,
+                          seconds: 2.5,
+                      });
+        ::action_set_map(&BackupResult {
+                bricks,
+                backup_outcome: "success".to_string(),
+            })
+            .unwrap();
+
+        let results = model.action_results();
+        assert_eq!(results["bricks.brick-1.size"], "1024");
+        assert_eq!(results["bricks.brick-1.seconds"], "2.5");
+        assert_eq!(results["backup-outcome"], "success");
+        assert!(::action_set_map(&vec![1, 2]).is_err());
+    }
+
+    #[test]
+    fn action_results_must_use_keys_juju_accepts() {
+        use runner::HookToolRunner;
+
+        let (model, _runner) = model();
+        for key in &["saved_to", "Size", "-size", "size-", "bricks..size", "bricks.brick 1", ""] {
+            let mut results = ::std::collections::HashMap::new();
+            results.insert(*key, "1");
+            assert!(::action_set_map(&results).is_err(), "{} was accepted", key);
+            assert!(::action_set(key, "1").is_err(), "{} was accepted", key);
+            let output = model.run("action-set", &[format!("{}=1", key)]).unwrap();
+            assert!(!output.succeeded(), "the fake accepted {}", key);
+        }
+        assert!(model.action_results().is_empty());
+        ::action_set("bricks.brick-1.size-mb", "1").unwrap();
+        assert_eq!(model.action_results()["bricks.brick-1.size-mb"], "1");
+    }
+
+    #[derive(Deserialize)]
+    struct BackupParams {
+        target: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct BackupResults {
+        saved_to: String,
+    }
+
+    fn backup(params: BackupParams) -> Result<BackupResults, String> {
+        if params.target.starts_with('/') {
+            Ok(BackupResults { saved_to: params.target })
+        } else {
+            Err(format!("{} is not an absolute path", params.target))
+        }
+    }
+
+    fn config_changed() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn process_actions_records_results() {
+        let (model, _runner) = model();
+        model.set_action_params(&[("target", "/backups")]);
+        model.run_action("backup", vec![action!("backup", backup)]).unwrap();
+        assert_eq!(model.action_results()["saved-to"], "/backups");
+        assert_eq!(model.action_failure(), None);
+    }
+
+    #[test]
+    fn process_actions_fails_the_action_on_error() {
+        let (model, _runner) = model();
+        model.set_action_params(&[("target", "backups")]);
+        let result = model.run_action("backup", vec![action!("backup", backup)]);
+        assert_eq!(result, Err("backups is not an absolute path".to_string()));
+        assert_eq!(model.action_failure(),
+                   Some("backups is not an absolute path".to_string()));
+
+        let model = ::testing::FakeModel::new("gluster/0");
+        assert!(model.run_action("backup", vec![action!("backup", backup)]).is_err());
+        assert!(model.action_failure().unwrap().starts_with("Invalid action parameters"));
+
+        let model = ::testing::FakeModel::new("gluster/0");
+        assert!(model.run_action("restore", vec![action!("backup", backup)]).is_err());
+        assert!(model.action_failure().is_some());
+    }
+
+    #[test]
+    fn dispatch_picks_actions_or_hooks() {
+        let (model, _runner) = model();
+        model.set_env("JUJU_HOOK_NAME", "config-changed");
+        ::dispatch(vec![hook!("config-changed", config_changed)],
+                   vec![action!("backup", backup)])
+            .unwrap();
+
+        model.set_env("JUJU_ACTION_NAME", "backup");
+        model.set_action_params(&[("target", "/backups")]);
+        ::dispatch(vec![hook!("config-changed", config_changed)],
+                   vec![action!("backup", backup)])
+            .unwrap();
+        assert_eq!(model.action_results()["saved-to"], "/backups");
+    }
+
+    #[test]
+    fn leader_set_is_refused_on_other_units() {
+        let (model, _runner) = model();
+        model.set_leader(false);
+        match ::leader_set(&[("cluster-id", "1234")]) {
+            Err(::JujuError::NotLeader) => {}
+            other => panic!("Expected NotLeader, got {:?}", other),
         }
-        let output = try!(cmd.output());
-        return Ok(output);
+        assert!(model.leader_data().is_empty());
+    }
+
+    #[test]
+    fn leader_settings_round_trip() {
+        let (model, _runner) = model();
+        assert!(::leader_get_all().unwrap().is_empty());
+        model.set_leader(true);
+        ::leader_set(&[("cluster-id", "1234"), ("password", "s3cret:x")]).unwrap();
+
+        assert_eq!(::leader_get("password").unwrap(), Some("s3cret:x".to_string()));
+        assert_eq!(::leader_get("missing").unwrap(), None);
+        let all = ::leader_get_all().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["cluster-id"], "1234");
+
+        ::leader_set(&[("cluster-id", "")]).unwrap();
+        assert_eq!(::leader_get("cluster-id").unwrap(), None);
+    }
+
+    #[test]
+    fn leader_get_or_set_only_generates_on_the_leader() {
+        let (model, _runner) = model();
+        let value = ::leader_get_or_set("password", || panic!("only the leader generates"));
+        assert_eq!(value.unwrap(), None);
+
+        model.set_leader(true);
+        assert_eq!(::leader_get_or_set("password", || "first".to_string()).unwrap(),
+                   Some("first".to_string()));
+        assert_eq!(::leader_get_or_set("password", || "second".to_string()).unwrap(),
+                   Some("first".to_string()));
+    }
+
+    #[test]
+    fn relation_get_all_by_id_returns_the_whole_bag() {
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.update_relation_data(&id,
+                                   "gluster/1",
+                                   &[("hostname", "gluster-1"), ("port", "24007")]);
+        model.add_relation_unit(&id, "gluster/2");
+        let relation: ::RelationId = id.parse().unwrap();
+
+        let settings = ::relation_get_all_by_id(&relation, &"gluster/1".parse().unwrap()).unwrap();
+        assert_eq!(settings.len(), 2);
+        assert_eq!(settings["hostname"], "gluster-1");
+        assert_eq!(settings["port"], "24007");
+        assert!(::relation_get_all_by_id(&relation, &"gluster/2".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn relation_by_id_functions_pass_the_relation_id_separately() {
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.update_relation_data(&id, "gluster/1", &[("hostname", "gluster-1")]);
+        model.add_relation_unit(&id, "gluster/2");
+        let relation: ::RelationId = id.parse().unwrap();
+
+        assert_eq!(::relation_ids_by_identifier("server").unwrap(), vec![relation.clone()]);
+        let units = ::relation_list_by_id(&relation).unwrap();
+        assert_eq!(units,
+                   vec!["gluster/1".parse::<::UnitName>().unwrap(),
+                        "gluster/2".parse().unwrap()]);
+        assert_eq!(::relation_get_by_id("hostname", &relation, &units[0]).unwrap(),
+                   "gluster-1");
+        ::relation_set_by_id("hostname", "gluster-0", &relation).unwrap();
+        assert_eq!(model.relation_data(&id, "gluster/0")["hostname"], "gluster-0");
+    }
+
+    #[test]
+    fn relation_set_many_passes_multi_line_values_in_a_file() {
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.set_env("JUJU_RELATION_ID", &id);
+        let certificate = "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+
+        ::relation_set_many(&[("hostname", "gluster-0"), ("port", "24007")]).unwrap();
+        ::relation_set_many(&[("certificate", certificate), ("port", "")]).unwrap();
+
+        let data = model.relation_data(&id, "gluster/0");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data["hostname"], "gluster-0");
+        assert_eq!(data["certificate"], certificate);
+    }
+
+    #[test]
+    fn relation_set_app_is_leader_only() {
+        let (model, _runner) = model();
+        let id = model.add_relation("db", "wordpress");
+        model.set_env("JUJU_RELATION_ID", &id);
+
+        match ::relation_set_app(&[("password", "s3cret")]) {
+            Err(::JujuError::NotLeader) => {}
+            other => panic!("expected NotLeader, got {:?}", other),
+        }
+        assert!(model.app_relation_data(&id, "gluster").is_empty());
+
+        model.set_leader(true);
+        ::relation_set_app(&[("password", "s3cret")]).unwrap();
+        assert_eq!(model.app_relation_data(&id, "gluster")["password"], "s3cret");
+        assert!(model.relation_data(&id, "gluster/0").is_empty());
+    }
+
+    #[test]
+    fn relation_get_app_reads_the_remote_application() {
+        let (model, _runner) = model();
+        let id = model.add_relation("db", "mysql");
+        model.update_app_relation_data(&id, "mysql", &[("user", "gluster")]);
+        model.update_relation_data(&id, "mysql/0", &[("user", "unit-data")]);
+        fn db_changed() -> Result<(), String> {
+            let app = ::relation_get_app().map_err(|e| e.to_string())?;
+            ::relation_set_many(&[("seen", &app["user"])]).map_err(|e| e.to_string())?;
+            Ok(())
+        }
+
+        let registry = vec![hook!("db-relation-changed", db_changed)];
+        model.run_relation_hook("db-relation-changed", &id, "mysql/0", registry).unwrap();
+        assert_eq!(model.relation_data(&id, "gluster/0")["seen"], "gluster");
+    }
+
+    #[test]
+    fn failed_tools_report_the_command_and_stderr() {
+        use runner::{self, HookToolRunner, ToolOutput};
+        use std::error::Error;
+
+        struct Failing;
+        impl HookToolRunner for Failing {
+            fn run(&self, _tool: &str, _args: &[String]) -> Result<ToolOutput, ::JujuError> {
+                Ok(ToolOutput::failure(2, "ERROR permission denied\n"))
+            }
+        }
+
+        let status = ::Status {
+            status_type: ::StatusType::Active,
+            message: "ready".to_string(),
+        };
+        let err = runner::with_runner(Failing, || ::status_set(status)).unwrap_err();
+        match err {
+            ::JujuError::CommandFailed { ref tool, ref args, exit_code, ref stderr } => {
+                assert_eq!(tool, "status-set");
+                assert_eq!(args, &["active".to_string(), "ready".to_string()]);
+                assert_eq!(exit_code, Some(2));
+                assert_eq!(stderr, "ERROR permission denied\n");
+            }
+            ref other => panic!("expected CommandFailed, got {:?}", other),
+        }
+        assert_eq!(err.to_string(),
+                   "status-set active ready failed with exit code 2: ERROR permission denied");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn missing_tools_and_bad_text_have_their_own_errors() {
+        use runner::{HookToolRunner, ProcessRunner};
+        use std::error::Error;
+
+        match ProcessRunner.run("juju-tool-that-does-not-exist", &[]) {
+            Err(::JujuError::ToolNotFound { ref tool }) => {
+                assert_eq!(tool, "juju-tool-that-does-not-exist")
+            }
+            other => panic!("expected ToolNotFound, got {:?}", other),
+        }
+        match model().0.run("credential-get", &[]) {
+            Err(::JujuError::ToolNotFound { .. }) => {}
+            other => panic!("expected ToolNotFound, got {:?}", other),
+        }
+
+        let err = "gluster".parse::<::UnitName>().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid unit name: \"gluster\"");
+        let err = ::JujuError::from("port".parse::<u16>().unwrap_err());
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn failing_tools_are_not_mistaken_for_empty_output() {
+        use runner::{self, HookToolRunner, ToolOutput};
+
+        let (model, _runner) = model();
+        let id = model.add_relation("server", "gluster");
+        model.set_env("JUJU_RELATION_ID", &id);
+        match ::relation_get("hostname") {
+            Err(::JujuError::CommandFailed { ref tool, ref stderr, .. }) => {
+                assert_eq!(tool, "relation-get");
+                assert!(stderr.contains("no unit or application specified"), "{}", stderr);
+            }
+            other => panic!("expected CommandFailed, got {:?}", other),
+        }
+        assert!(::relation_list().unwrap().is_empty());
+
+        struct Confused;
+        impl HookToolRunner for Confused {
+            fn run(&self, _tool: &str, _args: &[String]) -> Result<ToolOutput, ::JujuError> {
+                Ok(ToolOutput::success("maybe\n"))
+            }
+        }
+        match runner::with_runner(Confused, ::is_leader) {
+            Err(::JujuError::Parse { ref text, .. }) => assert_eq!(text, "maybe"),
+            other => panic!("expected Parse, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handlers_can_use_the_question_mark_operator() {
+        fn install() -> Result<(), String> {
+            ::status_set(::Status {
+                status_type: ::StatusType::Maintenance,
+                message: "installing".to_string(),
+            })?;
+            ::leader_set(&[("cluster-id", "1234")])?;
+            Ok(())
+        }
+
+        let (model, _runner) = model();
+        let err = model.run_hook("install", vec![hook!("install", install)]).unwrap_err();
+        assert!(err.contains("This unit is not the leader"), "{}", err);
+        assert_eq!(model.status(),
+                   Some(("maintenance".to_string(), "installing".to_string())));
+    }
+
+    #[test]
+    fn status_types_round_trip() {
+        use StatusType::*;
+        for status_type in &[Maintenance, Waiting, Active, Blocked, Error, Unknown, Terminated] {
+            assert_eq!(status_type.to_string().parse::<::StatusType>().unwrap(), *status_type);
+        }
+        assert!("Active".parse::<::StatusType>().is_err());
+    }
+
+    #[test]
+    fn status_get_parses_status_data() {
+        let output = r#"{"message": "ready", "status": "active", "status-data": {"port": 80}}"#;
+        let status: ::RawStatus = ::serde_json::from_str(output).unwrap();
+        let status = status.parse().unwrap();
+        assert_eq!(status.status.status_type, ::StatusType::Active);
+        assert_eq!(status.status.message, "ready");
+        assert_eq!(status.data["port"], 80);
+        assert!(status.units.is_empty());
+
+        let output = r#"{"message": "", "status": "exploded", "status-data": null}"#;
+        let status: ::RawStatus = ::serde_json::from_str(output).unwrap();
+        assert!(status.parse().is_err());
+    }
+
+    #[test]
+    fn status_set_rejects_statuses_only_juju_sets() {
+        let (model, _runner) = model();
+        model.set_leader(true);
+        for status_type in &[::StatusType::Error, ::StatusType::Unknown, ::StatusType::Terminated] {
+            let status = ::Status {
+                status_type: *status_type,
+                message: String::new(),
+            };
+            match ::status_set(status.clone()) {
+                Err(::JujuError::Parse { ref text, .. }) => {
+                    assert_eq!(*text, status_type.to_string())
+                }
+                other => panic!("expected a Parse error, got {:?}", other),
+            }
+            assert!(::status_set_application(status).is_err());
+        }
+        assert!(model.calls().iter().all(|(tool, _)| tool != "status-set"));
+        assert_eq!(model.status(), None);
+    }
+
+    #[test]
+    fn application_status_is_leader_only() {
+        let (model, _runner) = model();
+        let blocked = ::Status {
+            status_type: ::StatusType::Blocked,
+            message: "needs a database".to_string(),
+        };
+        match ::status_set_application(blocked.clone()) {
+            Err(::JujuError::NotLeader) => {}
+            other => panic!("expected NotLeader, got {:?}", other),
+        }
+        assert!(::status_get_application().is_err());
+
+        model.set_leader(true);
+        ::status_set_application(blocked.clone()).unwrap();
+        ::status_set(::Status {
+                status_type: ::StatusType::Waiting,
+                message: "waiting for the leader".to_string(),
+            })
+            .unwrap();
+        assert_eq!(model.application_status(),
+                   Some(("blocked".to_string(), "needs a database".to_string())));
+        let status = ::status_get_application().unwrap();
+        assert_eq!(status.status, blocked);
+        let unit: ::UnitName = "gluster/0".parse().unwrap();
+        assert_eq!(status.units[&unit].status.status_type, ::StatusType::Waiting);
+        assert_eq!(::status_get_info().unwrap().status.message, "waiting for the leader");
+        assert_eq!(::status_get().unwrap(), "waiting");
+    }
+
+    #[test]
+    fn config_reports_mismatched_types() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            replication_level: u32,
+        }
+
+        let (model, _runner) = model();
+        model.set_config("replication_level", "three");
+        assert!(::config::<Config>().is_err());
     }
 }
@@ -0,0 +1,100 @@
+//! A `log` crate backend that writes to the Juju debug-log.
+//!
+//! Once installed with `init_logger`, the `error!`, `info!`, `debug!`, ... macros write to
+//! juju-log.  While an action is running, records at or above the chosen action level are
+//! also sent to action-log so the operator can follow long running actions as they happen.
+//!
+//! # Examples
+//! ```
+//! extern crate juju;
+//! #[macro_use]
+//! extern crate log;
+//! use log::{LogLevel, LogLevelFilter};
+//!
+//! fn main() {
+//!     juju::logger::init_logger(LogLevelFilter::Debug, LogLevel::Info).unwrap();
+//!     info!("Rebalancing volume");
+//! }
+//! ```
+
+use log::{self, LogLevel, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+
+use runner;
+
+/// Sends log records to juju-log, and to action-log while an action is running
+#[derive(Debug)]
+pub struct JujuLogger {
+    max_level: LogLevelFilter,
+    action_level: LogLevel,
+}
+
+impl JujuLogger {
+    /// Log everything up to `max_level` to juju-log.  Records at `action_level` or above are
+    /// also sent to action-log while running an action.
+    pub fn new(max_level: LogLevelFilter, action_level: LogLevel) -> JujuLogger {
+        JujuLogger {
+            max_level,
+            action_level,
+        }
+    }
+
+    fn write(&self, level: LogLevel, message: &str) {
+        if level > self.max_level {
+            return;
+        }
+        ::log(message, Some(level));
+        if level <= self.action_level && runner::var("JUJU_ACTION_NAME").is_ok() {
+            // Like juju-log, feedback to the operator is best effort
+            let _ = ::action_log(message);
+        }
+    }
+}
+
+impl log::Log for JujuLogger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.max_level
+    }
+
+    fn log(&self, record: &LogRecord) {
+        self.write(record.level(), &record.args().to_string());
+    }
+}
+
+/// Install a `JujuLogger` as the global logger
+/// # Failures
+/// Returns SetLoggerError if a logger was already installed
+pub fn init_logger(max_level: LogLevelFilter,
+                   action_level: LogLevel)
+                   -> Result<(), SetLoggerError> {
+    log::set_logger(|max_log_level| {
+        max_log_level.set(max_level);
+        Box::new(JujuLogger::new(max_level, action_level))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{LogLevel, LogLevelFilter};
+
+    use testing::FakeModel;
+    use super::JujuLogger;
+
+    #[test]
+    fn it_mirrors_records_to_action_log_while_running_an_action() {
+        let model = FakeModel::new("gluster/0");
+        let _runner = model.install();
+        let logger = JujuLogger::new(LogLevelFilter::Debug, LogLevel::Info);
+
+        logger.write(LogLevel::Info, "outside of an action");
+        model.set_env("JUJU_ACTION_NAME", "rebalance");
+        logger.write(LogLevel::Info, "rebalancing brick1");
+        logger.write(LogLevel::Debug, "moved 10 files");
+        logger.write(LogLevel::Trace, "too detailed");
+
+        assert_eq!(model.action_logs(), vec!["rebalancing brick1".to_string()]);
+        assert_eq!(model.logs(),
+                   vec![(Some("INFO".to_string()), "outside of an action".to_string()),
+                        (Some("INFO".to_string()), "rebalancing brick1".to_string()),
+                        (Some("DEBUG".to_string()), "moved 10 files".to_string())]);
+    }
+}
@@ -18,6 +18,20 @@ macro_rules! hook {
     };
 }
 
+///
+/// A basic macro to ease creation and management
+/// of Actions
+///
+#[macro_export]
+macro_rules! action {
+    ($action_name:expr, $fn_name:expr) => {
+        $crate::Action::new($action_name, $fn_name)
+    };
+    ($action_name:ident $fn_name:ident) => {
+        $crate::Action::new(stringify!($action_name), $fn_name)
+    };
+}
+
 ///
 /// A Macro to set Juju's status
 ///
@@ -42,7 +56,7 @@ mod tests{
         }
     }
 
-    use super::super::Hook;
+    use super::super::{Hook, HookContext, HookEvent, HookHandler};
     fn cb() -> Result<(), String> {
         Ok(())
     }
@@ -65,4 +79,40 @@ mod tests{
         };
         assert_eq!(h1, h2);
     }
+
+    fn context_cb(context: &mut HookContext) -> Result<(), String> {
+        match context.event {
+            HookEvent::ConfigChanged => Ok(()),
+            ref other => Err(format!("unexpected event {}", other)),
+        }
+    }
+
+    #[test]
+    fn hooks_become_handlers() {
+        let mut h1: HookHandler = hook!(test cb).into();
+        assert_eq!(h1.name, "test");
+        let mut context = HookContext::new(HookEvent::Other("test".to_string()));
+        assert_eq!(h1.call(&mut context), Ok(()));
+    }
+
+    #[test]
+    fn it_makes_handlers_with_and_without_context() {
+        let mut h1 = HookHandler::from_handler("config-changed", cb);
+        let mut h2 = HookHandler::from_handler("config-changed", context_cb);
+        assert_eq!(h1.name, "config-changed");
+        assert_eq!(h2.name, "config-changed");
+        let mut context = HookContext::new(HookEvent::ConfigChanged);
+        assert_eq!(h1.call(&mut context), Ok(()));
+        assert_eq!(h2.call(&mut context), Ok(()));
+    }
+
+    fn noop(_params: ::serde_json::Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn it_makes_actions_correctly() {
+        assert_eq!(action!(backup noop).name, "backup");
+        assert_eq!(action!("set-password", noop).name, "set-password");
+    }
 }
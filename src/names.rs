@@ -0,0 +1,203 @@
+//! Typed names for the things hook tools refer to.
+//!
+//! Juju hands out relation ids like `server:3`, unit names like `gluster/2` and application
+//! names like `gluster`.  Giving each its own type means passing a unit where a relation id is
+//! expected is a compile error rather than a hook tool failure at deploy time.
+
+use std::fmt;
+use std::str::FromStr;
+
+use JujuError;
+
+/// Application names are lowercase letters, digits and hyphens.  They start with a letter and
+/// no hyphen separated part may be only digits, so that `mysql-0` can not be confused with a
+/// unit.
+fn valid_application(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase()) &&
+    name.split('-').all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) &&
+        part.chars().any(|c| c.is_ascii_lowercase())
+    })
+}
+
+/// Endpoint names, as declared in metadata.yaml, are lowercase letters and digits separated by
+/// single hyphens or underscores, starting with a letter
+fn valid_endpoint(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase()) &&
+    name.split(['-', '_']).all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    })
+}
+
+/// The name of an application, ie: gluster
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ApplicationName(String);
+
+impl ApplicationName {
+    /// The name as a string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ApplicationName {
+    type Err = JujuError;
+
+    fn from_str(s: &str) -> Result<ApplicationName, JujuError> {
+        if valid_application(s) {
+            Ok(ApplicationName(s.to_string()))
+        } else {
+            Err(JujuError::parse("Invalid application name", s))
+        }
+    }
+}
+
+impl fmt::Display for ApplicationName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ApplicationName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The name of a unit, ie: gluster/2
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UnitName {
+    application: ApplicationName,
+    number: usize,
+}
+
+impl UnitName {
+    /// The unit `number` of `application`
+    pub fn new(application: ApplicationName, number: usize) -> UnitName {
+        UnitName {
+            application,
+            number,
+        }
+    }
+
+    /// The application the unit belongs to
+    pub fn application(&self) -> &ApplicationName {
+        &self.application
+    }
+
+    /// The unit number, ie: 2 for gluster/2
+    pub fn number(&self) -> usize {
+        self.number
+    }
+}
+
+impl FromStr for UnitName {
+    type Err = JujuError;
+
+    fn from_str(s: &str) -> Result<UnitName, JujuError> {
+        let invalid = || JujuError::parse("Invalid unit name", s);
+        let (application, number) = match s.find('/') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => return Err(invalid()),
+        };
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        Ok(UnitName {
+            application: application.parse().map_err(|_| invalid())?,
+            number: number.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for UnitName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.application, self.number)
+    }
+}
+
+/// The id of a relation, ie: server:3.  Made up of the local endpoint name and a number that
+/// is unique within the model.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RelationId {
+    endpoint: String,
+    id: usize,
+}
+
+impl RelationId {
+    /// The relation `id` on `endpoint`
+    /// # Failures
+    /// Returns a JujuError if `endpoint` is not a valid endpoint name
+    pub fn new(endpoint: &str, id: usize) -> Result<RelationId, JujuError> {
+        if !valid_endpoint(endpoint) {
+            return Err(JujuError::parse("Invalid endpoint name", endpoint));
+        }
+        Ok(RelationId {
+            endpoint: endpoint.to_string(),
+            id,
+        })
+    }
+
+    /// The local endpoint name from metadata.yaml, ie: server
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The numeric part of the relation id, ie: 3 for server:3
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl FromStr for RelationId {
+    type Err = JujuError;
+
+    fn from_str(s: &str) -> Result<RelationId, JujuError> {
+        let invalid = || JujuError::parse("Invalid relation id", s);
+        let (endpoint, id) = match s.rfind(':') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => return Err(invalid()),
+        };
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        RelationId::new(endpoint, id.parse()?).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for RelationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.endpoint, self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApplicationName, RelationId, UnitName};
+
+    #[test]
+    fn it_parses_and_displays_names() {
+        let unit: UnitName = "gluster-fs/12".parse().unwrap();
+        assert_eq!(unit.application().as_str(), "gluster-fs");
+        assert_eq!(unit.number(), 12);
+        assert_eq!(unit.to_string(), "gluster-fs/12");
+
+        let id: RelationId = "brick_server:3".parse().unwrap();
+        assert_eq!(id.endpoint(), "brick_server");
+        assert_eq!(id.id(), 3);
+        assert_eq!(id.to_string(), "brick_server:3");
+    }
+
+    #[test]
+    fn it_rejects_invalid_names() {
+        for name in &["", "Gluster", "9p", "mysql-0", "gluster-", "my--sql", "gluster/0"] {
+            assert!(name.parse::<ApplicationName>().is_err(), "{} parsed", name);
+        }
+        for name in &["gluster", "gluster/", "gluster/x", "/0", "gluster/-1", "gluster:0"] {
+            assert!(name.parse::<UnitName>().is_err(), "{} parsed", name);
+        }
+        for name in &["server", "server:", ":3", "server:x", "Server:3", "gluster/0"] {
+            assert!(name.parse::<RelationId>().is_err(), "{} parsed", name);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Network information for relation endpoints from `network-get`.
+//!
+//! Each endpoint in metadata.yaml can be bound to a different network space.  network_get
+//! tells a charm which addresses to listen on for an endpoint and which address to hand out
+//! to the other side of its relations.
+
+use std::net::IpAddr;
+
+use {JujuError, RelationId};
+
+/// A single address configured on a network interface
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(from = "RawInterfaceAddress", into = "RawInterfaceAddress")]
+pub struct InterfaceAddress {
+    /// The address.  Usually an IP address, but may be a hostname
+    pub address: String,
+    /// The CIDR of the subnet the address is in, ie: 10.0.0.0/24
+    pub cidr: String,
+    /// The hostname for the address, if Juju knows it
+    pub hostname: String,
+}
+
+/// An address as network-get prints it.  Juju 2.8 and later print the address as both
+/// `address` and the older `value`, earlier versions only as `value`.
+#[derive(Deserialize, Serialize)]
+struct RawInterfaceAddress {
+    #[serde(default)]
+    address: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    cidr: String,
+    #[serde(default)]
+    hostname: String,
+}
+
+impl From<RawInterfaceAddress> for InterfaceAddress {
+    fn from(raw: RawInterfaceAddress) -> InterfaceAddress {
+        InterfaceAddress {
+            address: if raw.address.is_empty() { raw.value } else { raw.address },
+            cidr: raw.cidr,
+            hostname: raw.hostname,
+        }
+    }
+}
+
+impl From<InterfaceAddress> for RawInterfaceAddress {
+    fn from(address: InterfaceAddress) -> RawInterfaceAddress {
+        RawInterfaceAddress {
+            value: address.address.clone(),
+            address: address.address,
+            cidr: address.cidr,
+            hostname: address.hostname,
+        }
+    }
+}
+
+/// A network interface the unit should bind to for the endpoint
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(from = "RawBindAddress", into = "RawBindAddress")]
+pub struct BindAddress {
+    /// The name of the interface, ie: eth0
+    pub interface_name: String,
+    /// The MAC address of the interface
+    pub mac_address: String,
+    /// The addresses on the interface
+    pub addresses: Vec<InterfaceAddress>,
+}
+
+/// An interface as network-get prints it.  Like addresses, Juju 2.8 and later print the name
+/// and MAC address under both the hyphenated keys and the older unhyphenated ones.
+#[derive(Deserialize, Serialize)]
+struct RawBindAddress {
+    #[serde(rename = "interface-name", default)]
+    interface_name: String,
+    #[serde(default)]
+    interfacename: String,
+    #[serde(rename = "mac-address", default)]
+    mac_address: String,
+    #[serde(default)]
+    macaddress: String,
+    #[serde(default)]
+    addresses: Vec<InterfaceAddress>,
+}
+
+impl From<RawBindAddress> for BindAddress {
+    fn from(raw: RawBindAddress) -> BindAddress {
+        let or = |preferred: String, legacy: String| {
+            if preferred.is_empty() { legacy } else { preferred }
+        };
+        BindAddress {
+            interface_name: or(raw.interface_name, raw.interfacename),
+            mac_address: or(raw.mac_address, raw.macaddress),
+            addresses: raw.addresses,
+        }
+    }
+}
+
+impl From<BindAddress> for RawBindAddress {
+    fn from(bind: BindAddress) -> RawBindAddress {
+        RawBindAddress {
+            interfacename: bind.interface_name.clone(),
+            interface_name: bind.interface_name,
+            macaddress: bind.mac_address.clone(),
+            mac_address: bind.mac_address,
+            addresses: bind.addresses,
+        }
+    }
+}
+
+/// Everything network-get reports for an endpoint
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct NetworkInfo {
+    /// The interfaces and addresses to listen on
+    #[serde(rename = "bind-addresses", default)]
+    pub bind_addresses: Vec<BindAddress>,
+    /// The addresses other units should use to reach this unit, in order of preference
+    #[serde(rename = "ingress-addresses", default)]
+    pub ingress_addresses: Vec<String>,
+    /// The subnets, in CIDR notation, traffic from this unit will come from
+    #[serde(rename = "egress-subnets", default)]
+    pub egress_subnets: Vec<String>,
+}
+
+impl NetworkInfo {
+    /// The preferred address for other units to reach this one
+    pub fn ingress_address(&self) -> Option<&str> {
+        self.ingress_addresses.first().map(|a| a.as_ref())
+    }
+
+    /// The first bind address that is an IP address, for services that want to listen on a
+    /// single address
+    pub fn bind_address(&self) -> Option<IpAddr> {
+        self.bind_addresses
+            .iter()
+            .flat_map(|b| b.addresses.iter())
+            .filter_map(|a| a.address.parse::<IpAddr>().ok())
+            .next()
+    }
+}
+
+/// Get the network information for `endpoint`, ie: the relation name from metadata.yaml
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     model.set_addresses("192.168.1.10", "203.0.113.10");
+///     let _runner = model.install();
+///
+///     let info = juju::network_get("server").unwrap();
+///     assert_eq!(info.ingress_address(), Some("192.168.1.10"));
+///     assert_eq!(info.bind_address(), Some("192.168.1.10".parse().unwrap()));
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if network-get fails or its output can not be parsed
+pub fn network_get(endpoint: &str) -> Result<NetworkInfo, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string(), endpoint.to_string()];
+    ::run_json("network-get", &arg_list)
+}
+
+/// Get the network information for `endpoint` as seen by a specific relation.  The ingress
+/// address can differ between relations, for example for cross model relations.
+/// # Failures
+/// Returns a JujuError if network-get fails or its output can not be parsed
+pub fn network_get_by_id(endpoint: &str, id: &RelationId) -> Result<NetworkInfo, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string(),
+                                     "-r".to_string(),
+                                     id.to_string(),
+                                     endpoint.to_string()];
+    ::run_json("network-get", &arg_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::NetworkInfo;
+
+    #[test]
+    fn it_parses_network_get_output() {
+        let output = r#"{
+          "bind-addresses": [{
+            "mac-address": "00:16:3e:12:34:56",
+            "interface-name": "eth1",
+            "addresses": [
+              {"hostname": "", "address": "fe80::1", "cidr": "fe80::/64"},
+              {"hostname": "", "value": "10.20.0.5", "cidr": "10.20.0.0/24"}
+            ]
+          }],
+          "egress-subnets": ["10.20.0.5/32"],
+          "ingress-addresses": ["gluster-0.internal", "10.20.0.5"]
+        }"#;
+        let info: NetworkInfo = serde_json::from_str(output).unwrap();
+
+        assert_eq!(info.bind_addresses[0].interface_name, "eth1");
+        assert_eq!(info.bind_addresses[0].addresses[1].address, "10.20.0.5");
+        assert_eq!(info.bind_addresses[0].addresses[1].cidr, "10.20.0.0/24");
+        assert_eq!(info.egress_subnets, vec!["10.20.0.5/32".to_string()]);
+        assert_eq!(info.ingress_address(), Some("gluster-0.internal"));
+        assert_eq!(info.bind_address(), Some("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn it_parses_output_with_both_address_keys() {
+        // network-get --format=json from a Juju 2.9 machine unit, which prints every address
+        // and interface field under both its current and its legacy key
+        let output = r#"{
+          "bind-addresses": [{
+            "mac-address": "00:16:3e:a1:b2:c3",
+            "interface-name": "eth0",
+            "addresses": [
+              {"hostname": "", "address": "10.5.0.11", "cidr": "10.5.0.0/16", "value": "10.5.0.11"}
+            ],
+            "macaddress": "00:16:3e:a1:b2:c3",
+            "interfacename": "eth0"
+          }],
+          "egress-subnets": ["10.5.0.11/32"],
+          "ingress-addresses": ["10.5.0.11"]
+        }"#;
+        let info: NetworkInfo = serde_json::from_str(output).unwrap();
+
+        assert_eq!(info.bind_addresses[0].interface_name, "eth0");
+        assert_eq!(info.bind_addresses[0].mac_address, "00:16:3e:a1:b2:c3");
+        assert_eq!(info.bind_addresses[0].addresses[0].address, "10.5.0.11");
+        assert_eq!(info.bind_addresses[0].addresses[0].cidr, "10.5.0.0/16");
+        assert_eq!(info.bind_address(), Some("10.5.0.11".parse().unwrap()));
+
+        // What the library prints parses back the same, as FakeModel relies on
+        let printed = serde_json::to_string(&info).unwrap();
+        assert!(printed.contains(r#""value":"10.5.0.11""#));
+        assert_eq!(serde_json::from_str::<NetworkInfo>(&printed).unwrap(), info);
+    }
+
+    #[test]
+    fn it_tolerates_missing_sections() {
+        let info: NetworkInfo = serde_json::from_str("{}").unwrap();
+        assert_eq!(info.ingress_address(), None);
+        assert_eq!(info.bind_address(), None);
+    }
+}
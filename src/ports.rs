@@ -0,0 +1,233 @@
+//! Opening and closing ports with `open-port`, `close-port` and `opened-ports`.
+//!
+//! Ports a unit opens are only reachable from outside the model once the application is
+//! exposed.  A port can be a single port, a range of ports or ICMP, and can be opened for every
+//! endpoint or only for some of them.  set_ports is the easiest way to keep the open ports in
+//! line with the config: it works out what changed and only opens and closes the difference.
+
+use std::fmt;
+use std::str::FromStr;
+
+use {JujuError, Transport};
+
+/// A port or range of ports a unit can open
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Port {
+    /// The ports `from` to `to`, inclusive.  A single port has `from` equal to `to`.
+    Range {
+        from: u16,
+        to: u16,
+        transport: Transport,
+    },
+    /// ICMP, ie: to let the unit be pinged
+    Icmp,
+}
+
+impl Port {
+    /// A single tcp port
+    pub fn tcp(port: u16) -> Port {
+        Port::range(port, port, Transport::Tcp)
+    }
+
+    /// A single udp port
+    pub fn udp(port: u16) -> Port {
+        Port::range(port, port, Transport::Udp)
+    }
+
+    /// The ports `from` to `to`, inclusive
+    pub fn range(from: u16, to: u16, transport: Transport) -> Port {
+        Port::Range {
+            from,
+            to,
+            transport,
+        }
+    }
+}
+
+impl fmt::Display for Port {
+    /// Writes the port the way the port hook tools take it, ie: 80/tcp, 8000-8080/udp or icmp
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Port::Range { from, to, transport } if from == to => {
+                write!(f, "{}/{}", from, transport)
+            }
+            Port::Range { from, to, transport } => write!(f, "{}-{}/{}", from, to, transport),
+            Port::Icmp => f.write_str("icmp"),
+        }
+    }
+}
+
+impl FromStr for Port {
+    type Err = JujuError;
+
+    /// Parse a port as opened-ports prints it.  Like open-port, the transport defaults to tcp.
+    fn from_str(s: &str) -> Result<Port, JujuError> {
+        if s == "icmp" {
+            return Ok(Port::Icmp);
+        }
+        let invalid = || JujuError::parse("Invalid port", s);
+        let (range, transport) = match s.find('/') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => (s, "tcp"),
+        };
+        let transport = match transport {
+            "tcp" => Transport::Tcp,
+            "udp" => Transport::Udp,
+            _ => return Err(invalid()),
+        };
+        let (from, to) = match range.find('-') {
+            Some(index) => (&range[..index], &range[index + 1..]),
+            None => (range, range),
+        };
+        let from: u16 = from.parse().map_err(|_| invalid())?;
+        let to: u16 = to.parse().map_err(|_| invalid())?;
+        if from == 0 || from > to {
+            return Err(invalid());
+        }
+        Ok(Port::range(from, to, transport))
+    }
+}
+
+/// Open `port` for `endpoints`, ie: the relation names from metadata.yaml.  Pass no endpoints to
+/// open it for all of them.
+/// # Failures
+/// Returns a JujuError if open-port fails
+pub fn open_port_on(port: Port, endpoints: &[&str]) -> Result<i32, JujuError> {
+    ::run_for_exit_code("open-port", &port_args(port, endpoints))
+}
+
+/// Close `port` for `endpoints`.  Pass no endpoints to close it for all of them.
+/// # Failures
+/// Returns a JujuError if close-port fails
+pub fn close_port_on(port: Port, endpoints: &[&str]) -> Result<i32, JujuError> {
+    ::run_for_exit_code("close-port", &port_args(port, endpoints))
+}
+
+fn port_args(port: Port, endpoints: &[&str]) -> Vec<String> {
+    let mut arg_list: Vec<String> = vec![port.to_string()];
+    if !endpoints.is_empty() {
+        arg_list.push("--endpoints".to_string());
+        arg_list.push(endpoints.join(","));
+    }
+    arg_list
+}
+
+/// The ports this unit has open, whatever endpoints they are open for
+/// # Failures
+/// Returns a JujuError if opened-ports fails or its output can not be parsed
+pub fn opened_ports() -> Result<Vec<Port>, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string()];
+    let ports: Vec<String> = ::run_json("opened-ports", &arg_list)?;
+    ports.iter().map(|port| port.parse()).collect()
+}
+
+/// The ports this unit has open along with the endpoint each is open for.  Ports open for every
+/// endpoint have None as their endpoint.
+/// # Failures
+/// Returns a JujuError if opened-ports fails or its output can not be parsed
+pub fn opened_ports_by_endpoint() -> Result<Vec<(Port, Option<String>)>, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string(), "--endpoints".to_string()];
+    let ports: Vec<String> = ::run_json("opened-ports", &arg_list)?;
+    ports.iter()
+        .map(|entry| {
+            // Each entry looks like `80/tcp (website)`, with `*` meaning every endpoint
+            let (port, endpoint) = match (entry.find(" ("), entry.ends_with(')')) {
+                (Some(index), true) => (&entry[..index], &entry[index + 2..entry.len() - 1]),
+                _ => return Err(JujuError::parse("Invalid opened-ports entry", entry)),
+            };
+            let endpoint = if endpoint == "*" { None } else { Some(endpoint.to_string()) };
+            Ok((port.parse()?, endpoint))
+        })
+        .collect()
+}
+
+/// Make `desired` the only ports this unit has open.  Ports that are open but not desired are
+/// closed for the endpoints they are open for and desired ports that are not open for every
+/// endpoint yet are opened for all of them, so changing the port in the config does not leave
+/// the old one open.  Ports that are already open as desired are left alone.
+/// # Examples
+/// ```
+/// extern crate juju;
+/// use juju::Port;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("web/0");
+///     let _runner = model.install();
+///
+///     juju::set_ports(&[Port::tcp(80), Port::tcp(443)]).unwrap();
+///     juju::set_ports(&[Port::tcp(8080), Port::tcp(443)]).unwrap();
+///     assert_eq!(juju::opened_ports().unwrap(), vec![Port::tcp(443), Port::tcp(8080)]);
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if opened-ports, open-port or close-port fail
+pub fn set_ports(desired: &[Port]) -> Result<i32, JujuError> {
+    let current = opened_ports_by_endpoint()?;
+    for (port, endpoint) in current.iter().filter(|(port, _)| !desired.contains(port)) {
+        match *endpoint {
+            Some(ref endpoint) => close_port_on(*port, &[endpoint])?,
+            None => close_port_on(*port, &[])?,
+        };
+    }
+    for port in desired.iter().filter(|port| !current.contains(&(**port, None))) {
+        open_port_on(*port, &[])?;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Port;
+    use testing::FakeModel;
+    use Transport;
+
+    #[test]
+    fn it_parses_and_displays_ports() {
+        for port in &["80/tcp", "53/udp", "8000-8080/tcp", "icmp"] {
+            assert_eq!(port.parse::<Port>().unwrap().to_string(), *port);
+        }
+        assert_eq!("22".parse::<Port>().unwrap(), Port::tcp(22));
+        assert_eq!("60000-61000/udp".parse::<Port>().unwrap(),
+                   Port::range(60000, 61000, Transport::Udp));
+        for port in &["", "0/tcp", "80/sctp", "8080-80/tcp", "70000/tcp", "http/tcp", "80-/tcp"] {
+            assert!(port.parse::<Port>().is_err(), "{} parsed", port);
+        }
+    }
+
+    #[test]
+    fn set_ports_only_changes_the_difference() {
+        let model = FakeModel::new("web/0");
+        let _runner = model.install();
+        ::open_port_on(Port::Icmp, &[]).unwrap();
+        ::open_port_on(Port::tcp(80), &["website"]).unwrap();
+        ::open_port_on(Port::tcp(443), &[]).unwrap();
+        ::open_port_on(Port::udp(53), &["dns"]).unwrap();
+        assert_eq!(::opened_ports_by_endpoint().unwrap(),
+                   vec![(Port::tcp(443), None),
+                        (Port::udp(53), Some("dns".to_string())),
+                        (Port::tcp(80), Some("website".to_string())),
+                        (Port::Icmp, None)]);
+
+        let before = model.calls().len();
+        ::set_ports(&[Port::tcp(80), Port::tcp(443), Port::range(8000, 8010, Transport::Tcp)])
+            .unwrap();
+        let changes: Vec<(String, Vec<String>)> = model.calls()[before..]
+            .iter()
+            .filter(|(tool, _)| tool != "opened-ports")
+            .cloned()
+            .collect();
+        let call = |tool: &str, args: &[&str]| {
+            (tool.to_string(), args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>())
+        };
+        assert_eq!(changes,
+                   vec![call("close-port", &["53/udp", "--endpoints", "dns"]),
+                        call("close-port", &["icmp"]),
+                        call("open-port", &["80/tcp"]),
+                        call("open-port", &["8000-8010/tcp"])]);
+        assert_eq!(model.opened_ports(),
+                   vec!["443/tcp".to_string(), "80/tcp".to_string(), "8000-8010/tcp".to_string()]);
+
+        ::set_ports(&[]).unwrap();
+        assert!(::opened_ports().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! Every hook tool (`config-get`, `relation-set`, `status-set`, ...) is invoked through a
+//! `HookToolRunner`.  By default the tools are spawned as processes, exactly as Juju expects.
+//! Installing a different runner on the current thread lets charm code run without a live
+//! Juju unit, which is what makes unit testing a charm possible.  A runner can also be given
+//! to a single `HookContext` with `HookContext::with_runner`, in which case it answers the
+//! calls made while that context's hook runs whatever is installed on the thread.
+//!
+//! # Examples
+//! ```
+//! extern crate juju;
+//! use juju::{HookToolRunner, JujuError, ToolOutput};
+//!
+//! struct AlwaysLeader;
+//!
+//! impl HookToolRunner for AlwaysLeader {
+//!     fn run(&self, tool: &str, _args: &[String]) -> Result<ToolOutput, JujuError> {
+//!         match tool {
+//!             "is-leader" => Ok(ToolOutput::success("True\n")),
+//!             _ => Ok(ToolOutput::failure(1, &format!("{} not faked", tool))),
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let leader = juju::runner::with_runner(AlwaysLeader, || juju::is_leader().unwrap());
+//!     assert!(leader);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::env;
+use std::io;
+use std::process::Command;
+use std::rc::Rc;
+
+use JujuError;
+
+/// The captured result of running a single hook tool
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolOutput {
+    /// Everything the tool wrote to stdout
+    pub stdout: Vec<u8>,
+    /// Everything the tool wrote to stderr
+    pub stderr: Vec<u8>,
+    /// The exit code of the tool.  None if it was terminated by a signal
+    pub exit_code: Option<i32>,
+}
+
+impl ToolOutput {
+    /// A successful run that printed `stdout`
+    pub fn success(stdout: &str) -> ToolOutput {
+        ToolOutput {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+            exit_code: Some(0),
+        }
+    }
+
+    /// A failed run that exited with `exit_code` and printed `stderr`
+    pub fn failure(exit_code: i32, stderr: &str) -> ToolOutput {
+        ToolOutput {
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+            exit_code: Some(exit_code),
+        }
+    }
+
+    /// Returns true if the tool exited with a zero exit code
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+impl From<std::process::Output> for ToolOutput {
+    fn from(output: std::process::Output) -> ToolOutput {
+        ToolOutput {
+            stdout: output.stdout,
+            stderr: output.stderr,
+            exit_code: output.status.code(),
+        }
+    }
+}
+
+/// Runs Juju hook tools on behalf of the library.
+/// Implement this to answer hook tool calls from somewhere other than a live Juju unit.
+pub trait HookToolRunner {
+    /// Invoke `tool` with `args` and return what it printed along with its exit code
+    /// # Failures
+    /// Returns a JujuError if the tool could not be run at all
+    fn run(&self, tool: &str, args: &[String]) -> Result<ToolOutput, JujuError>;
+
+    /// Look up a variable from the hook environment (`JUJU_HOOK_NAME`, `JUJU_RELATION_ID`, ...).
+    /// Defaults to the environment of the current process.
+    fn env_var(&self, key: &str) -> Result<String, env::VarError> {
+        env::var(key)
+    }
+}
+
+/// The default runner which spawns each hook tool as a child process
+#[derive(Debug, Default)]
+pub struct ProcessRunner;
+
+impl HookToolRunner for ProcessRunner {
+    fn run(&self, tool: &str, args: &[String]) -> Result<ToolOutput, JujuError> {
+        match Command::new(tool).args(args).output() {
+            Ok(output) => Ok(ToolOutput::from(output)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                Err(JujuError::ToolNotFound { tool: tool.to_string() })
+            }
+            Err(err) => Err(JujuError::from(err)),
+        }
+    }
+}
+
+thread_local! {
+    static RUNNER: RefCell<Option<Rc<dyn HookToolRunner>>> = const { RefCell::new(None) };
+}
+
+/// Install `runner` for every hook tool call made on the current thread.
+/// Returns the previously installed runner, if there was one.
+pub fn set_runner<R: HookToolRunner + 'static>(runner: R) -> Option<Rc<dyn HookToolRunner>> {
+    set_shared_runner(Rc::new(runner))
+}
+
+/// Install an already shared runner for the current thread.  This is useful when the caller
+/// wants to keep a handle to the runner to inspect it later.
+pub fn set_shared_runner(runner: Rc<dyn HookToolRunner>) -> Option<Rc<dyn HookToolRunner>> {
+    RUNNER.with(|r| r.borrow_mut().replace(runner))
+}
+
+/// Remove any runner installed on the current thread, going back to spawning processes
+pub fn reset_runner() -> Option<Rc<dyn HookToolRunner>> {
+    RUNNER.with(|r| r.borrow_mut().take())
+}
+
+/// Puts back the runner that was installed on the thread before `install_runner` when it is
+/// dropped
+#[must_use = "the previous runner is restored as soon as the guard is dropped"]
+pub struct RunnerGuard {
+    previous: Option<Rc<dyn HookToolRunner>>,
+}
+
+impl Drop for RunnerGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RUNNER.with(|r| *r.borrow_mut() = previous);
+    }
+}
+
+/// Install `runner` on the current thread until the returned guard is dropped
+pub fn install_runner<R: HookToolRunner + 'static>(runner: R) -> RunnerGuard {
+    install_shared_runner(Rc::new(runner))
+}
+
+/// Install an already shared runner on the current thread until the returned guard is dropped
+pub fn install_shared_runner(runner: Rc<dyn HookToolRunner>) -> RunnerGuard {
+    RunnerGuard { previous: set_shared_runner(runner) }
+}
+
+/// Run `f` with `runner` installed on the current thread, restoring the previous runner after
+pub fn with_runner<R, F, T>(runner: R, f: F) -> T
+    where R: HookToolRunner + 'static,
+          F: FnOnce() -> T
+{
+    let _guard = install_runner(runner);
+    f()
+}
+
+/// Look up a hook environment variable through the runner installed on this thread, or from
+/// the process environment if there is none
+pub fn var(key: &str) -> Result<String, env::VarError> {
+    let runner = RUNNER.with(|r| r.borrow().clone());
+    match runner {
+        Some(runner) => runner.env_var(key),
+        None => env::var(key),
+    }
+}
+
+/// Run a hook tool through the runner installed on this thread, or spawn it as a process if
+/// there is none.  When `as_root` is true the tool is run through sudo.
+pub fn run(tool: &str, args: &[String], as_root: bool) -> Result<ToolOutput, JujuError> {
+    // Clone the handle out so a runner is free to call back into the library
+    let runner = RUNNER.with(|r| r.borrow().clone());
+    let runner: Rc<dyn HookToolRunner> = match runner {
+        Some(runner) => runner,
+        None => Rc::new(ProcessRunner),
+    };
+    if as_root {
+        let mut sudo_args = vec![tool.to_string()];
+        sudo_args.extend_from_slice(args);
+        runner.run("sudo", &sudo_args)
+    } else {
+        runner.run(tool, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<(String, Vec<String>)>>,
+    }
+
+    impl HookToolRunner for Rc<Recorder> {
+        fn run(&self, tool: &str, args: &[String]) -> Result<ToolOutput, JujuError> {
+            self.calls.borrow_mut().push((tool.to_string(), args.to_vec()));
+            Ok(ToolOutput::success("False\n"))
+        }
+    }
+
+    #[test]
+    fn it_routes_tool_calls_through_the_installed_runner() {
+        let recorder = Rc::new(Recorder::default());
+        let leader = with_runner(recorder.clone(), || ::is_leader().unwrap());
+        assert!(!leader);
+        assert_eq!(*recorder.calls.borrow(),
+                   vec![("is-leader".to_string(), Vec::new())]);
+    }
+
+    #[test]
+    fn it_runs_root_tools_through_sudo() {
+        let recorder = Rc::new(Recorder::default());
+        with_runner(recorder.clone(), || ::reboot().unwrap());
+        assert_eq!(*recorder.calls.borrow(),
+                   vec![("sudo".to_string(), vec!["juju-reboot".to_string()])]);
+    }
+
+    #[test]
+    fn it_restores_the_previous_runner() {
+        let outer = Rc::new(Recorder::default());
+        let inner = Rc::new(Recorder::default());
+        set_runner(outer.clone());
+        with_runner(inner.clone(), || ::is_leader().unwrap());
+        ::is_leader().unwrap();
+        reset_runner();
+        assert_eq!(inner.calls.borrow().len(), 1);
+        assert_eq!(outer.calls.borrow().len(), 1);
+    }
+
+    #[test]
+    fn the_guard_restores_the_previous_runner() {
+        let outer = Rc::new(Recorder::default());
+        let inner = Rc::new(Recorder::default());
+        let _outer_guard = install_runner(outer.clone());
+        {
+            let _inner_guard = install_runner(inner.clone());
+            ::is_leader().unwrap();
+        }
+        ::is_leader().unwrap();
+        assert_eq!(inner.calls.borrow().len(), 1);
+        assert_eq!(outer.calls.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,509 @@
+//! Juju secrets, for sharing passwords and keys without putting them in relation data.
+//!
+//! A secret is created by its owner with secret_add, which returns the secret's URI.  The owner
+//! hands the URI to another application, usually through relation data, and grants that
+//! relation access with secret_grant.  The other side reads the content with secret_get.
+//! Secret content is a map of keys to values and every change to it makes a new revision.
+//! Consumers keep seeing the revision they last read until they refresh, and are told about a
+//! new one with a secret-changed hook.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use {JujuError, RelationId, UnitName};
+
+/// The content of a secret revision
+pub type SecretContent = HashMap<String, String>;
+
+/// The URI Juju identifies a secret by, ie: secret:cj4v5vm78ohs79o84r4g
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SecretUri {
+    model_uuid: Option<String>,
+    id: String,
+}
+
+impl SecretUri {
+    /// The unique id of the secret, without the `secret:` prefix
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The model the secret belongs to, if the URI names one.  Secrets shared across models
+    /// have URIs like secret://<model uuid>/<id>
+    pub fn model_uuid(&self) -> Option<&str> {
+        self.model_uuid.as_ref().map(|uuid| uuid.as_ref())
+    }
+}
+
+impl FromStr for SecretUri {
+    type Err = JujuError;
+
+    /// Parse `secret:<id>`, `secret://<model uuid>/<id>` or a bare id
+    fn from_str(s: &str) -> Result<SecretUri, JujuError> {
+        let rest = s.strip_prefix("secret:").unwrap_or(s);
+        let (model_uuid, id) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                match rest.find('/') {
+                    Some(index) if index > 0 => {
+                        (Some(rest[..index].to_string()), &rest[index + 1..])
+                    }
+                    _ => return Err(JujuError::parse("Invalid secret URI", s)),
+                }
+            }
+            None => (None, rest),
+        };
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+            return Err(JujuError::parse("Invalid secret URI", s));
+        }
+        Ok(SecretUri {
+            model_uuid,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for SecretUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.model_uuid {
+            Some(ref uuid) => write!(f, "secret://{}/{}", uuid, self.id),
+            None => write!(f, "secret:{}", self.id),
+        }
+    }
+}
+
+/// How often Juju should ask the owner to rotate a secret with a secret-rotate hook
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RotatePolicy {
+    Never,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl fmt::Display for RotatePolicy {
+    /// Writes the lowercase name Juju uses for the policy
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RotatePolicy::Never => write!(f, "never"),
+            RotatePolicy::Hourly => write!(f, "hourly"),
+            RotatePolicy::Daily => write!(f, "daily"),
+            RotatePolicy::Weekly => write!(f, "weekly"),
+            RotatePolicy::Monthly => write!(f, "monthly"),
+            RotatePolicy::Quarterly => write!(f, "quarterly"),
+            RotatePolicy::Yearly => write!(f, "yearly"),
+        }
+    }
+}
+
+impl FromStr for RotatePolicy {
+    type Err = JujuError;
+
+    fn from_str(s: &str) -> Result<RotatePolicy, JujuError> {
+        match s {
+            "never" => Ok(RotatePolicy::Never),
+            "hourly" => Ok(RotatePolicy::Hourly),
+            "daily" => Ok(RotatePolicy::Daily),
+            "weekly" => Ok(RotatePolicy::Weekly),
+            "monthly" => Ok(RotatePolicy::Monthly),
+            "quarterly" => Ok(RotatePolicy::Quarterly),
+            "yearly" => Ok(RotatePolicy::Yearly),
+            _ => Err(JujuError::parse("Invalid rotate policy", s)),
+        }
+    }
+}
+
+/// Who owns a secret.  Only the leader may change a secret owned by the application.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretOwner {
+    Application,
+    Unit,
+}
+
+impl fmt::Display for SecretOwner {
+    /// Writes the lowercase name Juju uses for the owner
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SecretOwner::Application => write!(f, "application"),
+            SecretOwner::Unit => write!(f, "unit"),
+        }
+    }
+}
+
+/// Metadata to set along with the content in secret_add and secret_set.  Fields left as None
+/// are not changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SecretOptions {
+    /// A label the owner can use to look the secret up instead of its URI
+    pub label: Option<String>,
+    /// A description of what the secret is for
+    pub description: Option<String>,
+    /// How long until the current revision expires and the owner gets a secret-expired hook
+    pub expire: Option<Duration>,
+    /// How often the owner gets a secret-rotate hook
+    pub rotate: Option<RotatePolicy>,
+    /// Who owns the secret.  Juju defaults to the application.
+    pub owner: Option<SecretOwner>,
+}
+
+impl SecretOptions {
+    fn to_args(&self) -> Vec<String> {
+        let mut arg_list: Vec<String> = Vec::new();
+        if let Some(ref label) = self.label {
+            arg_list.push("--label".to_string());
+            arg_list.push(label.clone());
+        }
+        if let Some(ref description) = self.description {
+            arg_list.push("--description".to_string());
+            arg_list.push(description.clone());
+        }
+        if let Some(expire) = self.expire {
+            arg_list.push("--expire".to_string());
+            arg_list.push(format!("{}s", expire.as_secs()));
+        }
+        if let Some(rotate) = self.rotate {
+            arg_list.push("--rotate".to_string());
+            arg_list.push(rotate.to_string());
+        }
+        if let Some(owner) = self.owner {
+            arg_list.push("--owner".to_string());
+            arg_list.push(owner.to_string());
+        }
+        arg_list
+    }
+}
+
+/// What secret-info-get reports about a secret the unit owns
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretInfo {
+    /// The URI of the secret
+    pub uri: SecretUri,
+    /// The latest revision of the content
+    pub revision: u32,
+    /// The label the owner gave the secret
+    pub label: Option<String>,
+    /// The description the owner gave the secret
+    pub description: Option<String>,
+    /// Who owns the secret
+    pub owner: SecretOwner,
+    /// The rotate policy, if one was set
+    pub rotation: Option<RotatePolicy>,
+    /// When the current revision expires, as an RFC 3339 timestamp
+    pub expiry: Option<String>,
+    /// When the secret is next due to be rotated, as an RFC 3339 timestamp
+    pub rotates: Option<String>,
+}
+
+/// secret-info-get output, keyed by secret id
+#[derive(Deserialize)]
+struct RawSecretInfo {
+    revision: u32,
+    label: Option<String>,
+    description: Option<String>,
+    owner: SecretOwner,
+    rotation: Option<RotatePolicy>,
+    expiry: Option<String>,
+    rotates: Option<String>,
+}
+
+/// Create a secret owned by this unit's application, or by the unit if `options` say so.
+/// Returns the URI to share with the applications that should be able to read it.  The content
+/// is passed to Juju in a file rather than on the command line so it does not show up in the
+/// process list.
+/// # Examples
+/// ```
+/// extern crate juju;
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("mysql/0");
+///     model.set_leader(true);
+///     let _runner = model.install();
+///
+///     let options = juju::SecretOptions {
+///         label: Some("root-password".to_string()),
+///         rotate: Some(juju::RotatePolicy::Monthly),
+///         ..Default::default()
+///     };
+///     let uri = juju::secret_add(&[("password", "s3cret")], &options).unwrap();
+///     assert_eq!(juju::secret_get(&uri).unwrap()["password"], "s3cret");
+/// }
+/// ```
+/// # Failures
+/// Returns a JujuError if secret-add fails, ie: when a unit that is not the leader adds a
+/// secret owned by the application
+pub fn secret_add(content: &[(&str, &str)],
+                  options: &SecretOptions)
+                  -> Result<SecretUri, JujuError> {
+    let output = ::run_with_settings_file("secret-add", options.to_args(), content)?;
+    output.parse()
+}
+
+/// Get the content of the revision of a secret this unit is tracking
+/// # Failures
+/// Returns a JujuError if secret-get fails, ie: when the secret has not been granted to the
+/// unit, or its output can not be parsed
+pub fn secret_get(uri: &SecretUri) -> Result<SecretContent, JujuError> {
+    secret_get_with(vec![uri.to_string()])
+}
+
+/// Get the content of a secret by the label the owner, or this unit, gave it
+/// # Failures
+/// Returns a JujuError if secret-get fails or its output can not be parsed
+pub fn secret_get_by_label(label: &str) -> Result<SecretContent, JujuError> {
+    secret_get_with(vec!["--label".to_string(), label.to_string()])
+}
+
+/// Get the content of the latest revision of a secret without starting to track it
+/// # Failures
+/// Returns a JujuError if secret-get fails or its output can not be parsed
+pub fn secret_peek(uri: &SecretUri) -> Result<SecretContent, JujuError> {
+    secret_get_with(vec![uri.to_string(), "--peek".to_string()])
+}
+
+/// Get the content of the latest revision of a secret and track it from now on.  Call this
+/// from secret-changed to pick up the new content.
+/// # Failures
+/// Returns a JujuError if secret-get fails or its output can not be parsed
+pub fn secret_refresh(uri: &SecretUri) -> Result<SecretContent, JujuError> {
+    secret_get_with(vec![uri.to_string(), "--refresh".to_string()])
+}
+
+fn secret_get_with(mut arg_list: Vec<String>) -> Result<SecretContent, JujuError> {
+    arg_list.push("--format=json".to_string());
+    ::run_json("secret-get", &arg_list)
+}
+
+/// Create a new revision of a secret this unit owns and update its metadata.  Pass empty
+/// content to change only the metadata.  Consumers get a secret-changed hook for a new
+/// revision.
+/// # Failures
+/// Returns a JujuError if secret-set fails, ie: when the unit does not own the secret
+pub fn secret_set(uri: &SecretUri,
+                  content: &[(&str, &str)],
+                  options: &SecretOptions)
+                  -> Result<i32, JujuError> {
+    let mut arg_list: Vec<String> = vec![uri.to_string()];
+    arg_list.extend(options.to_args());
+    if content.is_empty() {
+        return ::run_for_exit_code("secret-set", &arg_list);
+    }
+    ::run_with_settings_file("secret-set", arg_list, content)?;
+    Ok(0)
+}
+
+/// Remove a secret this unit owns, along with every revision of it
+/// # Failures
+/// Returns a JujuError if secret-remove fails
+pub fn secret_remove(uri: &SecretUri) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string()];
+    ::run_for_exit_code("secret-remove", &arg_list)
+}
+
+/// Remove a single revision of a secret, ie: from secret-remove once no consumer tracks it
+/// any more
+/// # Failures
+/// Returns a JujuError if secret-remove fails
+pub fn secret_remove_revision(uri: &SecretUri, revision: u32) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string(),
+                                     "--revision".to_string(),
+                                     revision.to_string()];
+    ::run_for_exit_code("secret-remove", &arg_list)
+}
+
+/// Let the application on the other side of a relation read a secret
+/// # Failures
+/// Returns a JujuError if secret-grant fails
+pub fn secret_grant(uri: &SecretUri, id: &RelationId) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string(), "-r".to_string(), id.to_string()];
+    ::run_for_exit_code("secret-grant", &arg_list)
+}
+
+/// Let a single unit on the other side of a relation read a secret
+/// # Failures
+/// Returns a JujuError if secret-grant fails
+pub fn secret_grant_unit(uri: &SecretUri,
+                         id: &RelationId,
+                         unit: &UnitName)
+                         -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string(),
+                                     "-r".to_string(),
+                                     id.to_string(),
+                                     "--unit".to_string(),
+                                     unit.to_string()];
+    ::run_for_exit_code("secret-grant", &arg_list)
+}
+
+/// Take away access to a secret from the application on the other side of a relation
+/// # Failures
+/// Returns a JujuError if secret-revoke fails
+pub fn secret_revoke(uri: &SecretUri, id: &RelationId) -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string(), "-r".to_string(), id.to_string()];
+    ::run_for_exit_code("secret-revoke", &arg_list)
+}
+
+/// Take away access to a secret from a single unit on the other side of a relation
+/// # Failures
+/// Returns a JujuError if secret-revoke fails
+pub fn secret_revoke_unit(uri: &SecretUri,
+                          id: &RelationId,
+                          unit: &UnitName)
+                          -> Result<i32, JujuError> {
+    let arg_list: Vec<String> = vec![uri.to_string(),
+                                     "-r".to_string(),
+                                     id.to_string(),
+                                     "--unit".to_string(),
+                                     unit.to_string()];
+    ::run_for_exit_code("secret-revoke", &arg_list)
+}
+
+/// The URIs of every secret this unit owns
+/// # Failures
+/// Returns a JujuError if secret-ids fails or its output can not be parsed
+pub fn secret_ids() -> Result<Vec<SecretUri>, JujuError> {
+    let arg_list: Vec<String> = vec!["--format=json".to_string()];
+    let ids: Vec<String> = ::run_json("secret-ids", &arg_list)?;
+    ids.iter().map(|id| id.parse()).collect()
+}
+
+/// Get the metadata of a secret this unit owns
+/// # Failures
+/// Returns a JujuError if secret-info-get fails or its output can not be parsed
+pub fn secret_info_get(uri: &SecretUri) -> Result<SecretInfo, JujuError> {
+    secret_info_get_with(vec![uri.to_string()])
+}
+
+/// Get the metadata of a secret this unit owns by its label
+/// # Failures
+/// Returns a JujuError if secret-info-get fails or its output can not be parsed
+pub fn secret_info_get_by_label(label: &str) -> Result<SecretInfo, JujuError> {
+    secret_info_get_with(vec!["--label".to_string(), label.to_string()])
+}
+
+fn secret_info_get_with(mut arg_list: Vec<String>) -> Result<SecretInfo, JujuError> {
+    arg_list.push("--format=json".to_string());
+    let info: HashMap<String, RawSecretInfo> = ::run_json("secret-info-get", &arg_list)?;
+    let (id, raw) = match info.into_iter().next() {
+        Some(entry) => entry,
+        None => return Err(JujuError::parse("No secret in secret-info-get output", "{}")),
+    };
+    Ok(SecretInfo {
+        uri: id.parse()?,
+        revision: raw.revision,
+        label: raw.label.filter(|label| !label.is_empty()),
+        description: raw.description.filter(|description| !description.is_empty()),
+        owner: raw.owner,
+        rotation: raw.rotation,
+        expiry: raw.expiry,
+        rotates: raw.rotates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RotatePolicy, SecretOptions, SecretOwner, SecretUri};
+    use std::time::Duration;
+    use testing::FakeModel;
+    use {HookContext, HookRegistry, RelationId};
+
+    #[test]
+    fn it_parses_and_displays_secret_uris() {
+        let uri: SecretUri = "secret:cj4v5vm78ohs79o84r4g".parse().unwrap();
+        assert_eq!(uri.id(), "cj4v5vm78ohs79o84r4g");
+        assert_eq!(uri.model_uuid(), None);
+        assert_eq!(uri.to_string(), "secret:cj4v5vm78ohs79o84r4g");
+        assert_eq!("cj4v5vm78ohs79o84r4g".parse::<SecretUri>().unwrap(), uri);
+
+        let uri: SecretUri = "secret://f47ac10b-58cc-4372-a567-0e02b2c3d479/cj4v5vm78ohs79o84r4g"
+            .parse()
+            .unwrap();
+        assert_eq!(uri.model_uuid(), Some("f47ac10b-58cc-4372-a567-0e02b2c3d479"));
+        assert_eq!(uri.id(), "cj4v5vm78ohs79o84r4g");
+
+        for uri in &["", "secret:", "secret://model", "secret:///id", "secret:ABC"] {
+            assert!(uri.parse::<SecretUri>().is_err(), "{} parsed", uri);
+        }
+    }
+
+    #[test]
+    fn options_become_flags() {
+        let options = SecretOptions {
+            label: Some("db".to_string()),
+            expire: Some(Duration::from_secs(3600)),
+            rotate: Some(RotatePolicy::Weekly),
+            owner: Some(SecretOwner::Unit),
+            ..Default::default()
+        };
+        assert_eq!(options.to_args(),
+                   vec!["--label", "db", "--expire", "3600s", "--rotate", "weekly", "--owner",
+                        "unit"]);
+        assert_eq!("quarterly".parse::<RotatePolicy>().unwrap(), RotatePolicy::Quarterly);
+        assert!("fortnightly".parse::<RotatePolicy>().is_err());
+    }
+
+    #[test]
+    fn owners_add_grant_and_update_secrets() {
+        let model = FakeModel::new("mysql/0");
+        let _runner = model.install();
+        let id = model.add_relation("db", "wordpress");
+        let relation: RelationId = id.parse().unwrap();
+        let options = SecretOptions {
+            label: Some("root".to_string()),
+            rotate: Some(RotatePolicy::Daily),
+            ..Default::default()
+        };
+
+        assert!(::secret_add(&[("password", "s3cret")], &options).is_err());
+        model.set_leader(true);
+        let uri = ::secret_add(&[("password", "s3cret")], &options).unwrap();
+        ::secret_grant(&uri, &relation).unwrap();
+        assert_eq!(model.secret_grants(&uri.to_string()), vec![id.clone()]);
+        assert_eq!(::secret_ids().unwrap(), vec![uri.clone()]);
+
+        ::secret_set(&uri, &[("password", "n3w")], &SecretOptions::default()).unwrap();
+        assert_eq!(::secret_get_by_label("root").unwrap()["password"], "n3w");
+        let info = ::secret_info_get(&uri).unwrap();
+        assert_eq!(info.uri, uri);
+        assert_eq!(info.revision, 2);
+        assert_eq!(info.label, Some("root".to_string()));
+        assert_eq!(info.owner, SecretOwner::Application);
+        assert_eq!(info.rotation, Some(RotatePolicy::Daily));
+        assert_eq!(::secret_info_get_by_label("root").unwrap().revision, 2);
+
+        ::secret_remove_revision(&uri, 1).unwrap();
+        ::secret_revoke(&uri, &relation).unwrap();
+        assert!(model.secret_grants(&uri.to_string()).is_empty());
+        ::secret_remove(&uri).unwrap();
+        assert!(::secret_get(&uri).is_err());
+    }
+
+    #[test]
+    fn consumers_track_a_revision_until_they_refresh() {
+        let model = FakeModel::new("wordpress/0");
+        let uri = model.add_secret(&[("password", "s3cret")]);
+        model.update_secret(&uri, &[("password", "n3w")]);
+
+        let mut registry: HookRegistry = HookRegistry::new();
+        registry.on(&["secret-changed"], |ctx: &mut HookContext| {
+            let uri = ctx.secret_id.clone().ok_or("no secret id")?;
+            assert_eq!(ctx.secret_revision, Some(2));
+            assert_eq!(::secret_get(&uri)?["password"], "s3cret");
+            assert_eq!(::secret_peek(&uri)?["password"], "n3w");
+            assert_eq!(::secret_get(&uri)?["password"], "s3cret");
+            assert_eq!(::secret_refresh(&uri)?["password"], "n3w");
+            assert_eq!(::secret_get(&uri)?["password"], "n3w");
+            Ok(())
+        });
+        model.run_secret_hook("secret-changed", &uri, registry).unwrap();
+
+        let uri: SecretUri = uri.parse().unwrap();
+        let _runner = model.install();
+        assert!(::secret_set(&uri, &[("password", "mine")], &SecretOptions::default()).is_err());
+        assert_eq!(model.secret_content(&uri.to_string())["password"], "n3w");
+    }
+}
@@ -0,0 +1,156 @@
+//! Combining the statuses of several components into the one status a unit can have.
+//!
+//! A charm often looks after several independent things, such as storage, peers, TLS and the
+//! workload itself, and each of them has something to say about the unit's status.  If each
+//! calls status_set, whichever runs last wins and `juju status` flaps between them.  Instead
+//! each component adds its status to a `StatusCollector` and the most important one is set
+//! once at the end of the hook.
+
+use {JujuError, Status, StatusType};
+
+/// How important a status is when choosing which one to show.  Statuses only Juju can set
+/// rank below everything a charm can set.
+fn priority(status_type: StatusType) -> u8 {
+    match status_type {
+        StatusType::Blocked => 4,
+        StatusType::Maintenance => 3,
+        StatusType::Waiting => 2,
+        StatusType::Active => 1,
+        StatusType::Error | StatusType::Unknown | StatusType::Terminated => 0,
+    }
+}
+
+/// Collects the status of each component of a charm and sets the highest priority one:
+/// blocked, then maintenance, then waiting, then active.  The message combines the messages
+/// of every component in that state.  The `HookContext` handed to hook handlers has one that
+/// is committed after the hook has run.
+/// # Examples
+/// ```
+/// extern crate juju;
+/// use juju::{Status, StatusCollector, StatusType};
+///
+/// fn main() {
+///     let model = juju::testing::FakeModel::new("gluster/0");
+///     let _runner = model.install();
+///
+///     let mut collector = StatusCollector::new();
+///     collector.add("workload",
+///                   Status {
+///                       status_type: StatusType::Active,
+///                       message: String::new(),
+///                   });
+///     collector.add("peers",
+///                   Status {
+///                       status_type: StatusType::Waiting,
+///                       message: "2 of 3 peers".to_string(),
+///                   });
+///     collector.commit().unwrap();
+///     assert_eq!(model.status(),
+///                Some(("waiting".to_string(), "peers: 2 of 3 peers".to_string())));
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatusCollector {
+    statuses: Vec<(String, Status)>,
+}
+
+impl StatusCollector {
+    /// Constructs a new, empty `StatusCollector`
+    pub fn new() -> StatusCollector {
+        StatusCollector { statuses: Vec::new() }
+    }
+
+    /// Report the status of `component`.  A component that reports again replaces its earlier
+    /// status.
+    pub fn add(&mut self, component: &str, status: Status) -> &mut StatusCollector {
+        match self.statuses.iter_mut().find(|(name, _)| name == component) {
+            Some((_, existing)) => *existing = status,
+            None => self.statuses.push((component.to_string(), status)),
+        }
+        self
+    }
+
+    /// The status `component` reported, if it has reported one
+    pub fn get(&self, component: &str) -> Option<&Status> {
+        self.statuses.iter().find(|(name, _)| name == component).map(|(_, status)| status)
+    }
+
+    /// Whether no component has reported a status
+    pub fn is_empty(&self) -> bool {
+        self.statuses.is_empty()
+    }
+
+    /// The status that would be set: the highest priority state, with the messages of every
+    /// component in that state joined by `; ` in the order they were reported.  None if no
+    /// component has reported a status.
+    pub fn combined(&self) -> Option<Status> {
+        let status_type = self.statuses
+            .iter()
+            .map(|(_, status)| status.status_type)
+            .max_by_key(|status_type| priority(*status_type))?;
+        let messages: Vec<String> = self.statuses
+            .iter()
+            .filter(|(_, status)| status.status_type == status_type && !status.message.is_empty())
+            .map(|(component, status)| if component.is_empty() {
+                status.message.clone()
+            } else {
+                format!("{}: {}", component, status.message)
+            })
+            .collect();
+        Some(Status {
+            status_type,
+            message: messages.join("; "),
+        })
+    }
+
+    /// Set the combined status with a single status_set call and start collecting afresh.
+    /// Does nothing if no component has reported a status.
+    /// # Failures
+    /// Returns a JujuError if status-set fails
+    pub fn commit(&mut self) -> Result<i32, JujuError> {
+        let status = self.combined();
+        self.statuses.clear();
+        match status {
+            Some(status) => ::status_set(status),
+            None => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatusCollector;
+    use {Status, StatusType};
+
+    fn status(status_type: StatusType, message: &str) -> Status {
+        Status {
+            status_type,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_picks_the_highest_priority_state() {
+        let mut collector = StatusCollector::new();
+        assert_eq!(collector.combined(), None);
+
+        collector.add("workload", status(StatusType::Active, "serving"))
+            .add("peers", status(StatusType::Waiting, "2 of 3 peers"))
+            .add("tls", status(StatusType::Maintenance, "renewing certificate"))
+            .add("storage", status(StatusType::Maintenance, "formatting"));
+        assert_eq!(collector.combined(),
+                   Some(status(StatusType::Maintenance,
+                               "tls: renewing certificate; storage: formatting")));
+
+        collector.add("", status(StatusType::Blocked, "needs a brick"));
+        assert_eq!(collector.combined(), Some(status(StatusType::Blocked, "needs a brick")));
+        assert_eq!(collector.get(""), Some(&status(StatusType::Blocked, "needs a brick")));
+        assert_eq!(collector.get("peers"), Some(&status(StatusType::Waiting, "2 of 3 peers")));
+        assert_eq!(collector.get("metrics"), None);
+
+        collector.add("", status(StatusType::Active, ""))
+            .add("tls", status(StatusType::Active, ""))
+            .add("storage", status(StatusType::Active, ""));
+        assert_eq!(collector.combined(), Some(status(StatusType::Waiting, "peers: 2 of 3 peers")));
+    }
+}
@@ -0,0 +1,1292 @@
+//! An in-memory Juju model for testing charms without a Juju deployment.
+//!
+//! `FakeModel` answers the hook tools the library calls (`config-get`, `relation-get`,
+//! `status-set`, ...) from its own state.  Set up a scenario, run a hook through
+//! `process_hooks` and then assert on what the charm left behind.
+//!
+//! # Examples
+//! ```
+//! #[macro_use]
+//! extern crate juju;
+//! use juju::testing::FakeModel;
+//!
+//! fn config_changed() -> Result<(), String> {
+//!     let port = juju::config_get("port").map_err(|e| e.to_string())?;
+//!     let port = port.parse::<usize>().map_err(|e| e.to_string())?;
+//!     juju::open_port(port, juju::Transport::Tcp).map_err(|e| e.to_string())?;
+//!     status_set!(Active "serving");
+//!     Ok(())
+//! }
+//!
+//! fn main() {
+//!     let model = FakeModel::new("web/0");
+//!     model.set_config("port", "8080");
+//!
+//!     model.run_hook("config-changed", vec![hook!("config-changed", config_changed)])
+//!         .unwrap();
+//!
+//!     assert_eq!(model.opened_ports(), vec!["8080/tcp".to_string()]);
+//!     assert_eq!(model.status(), Some(("active".to_string(), "serving".to_string())));
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env::VarError;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
+
+use log::LogLevel;
+use serde_json::{self, Value};
+use serde_yaml;
+
+use network::{BindAddress, InterfaceAddress, NetworkInfo};
+use runner::{self, HookToolRunner, RunnerGuard, ToolOutput};
+use secrets::SecretUri;
+use {Action, HookRegistry, JujuError};
+
+/// A relation between the unit under test and a remote application
+#[derive(Clone, Debug, Default)]
+struct FakeRelation {
+    /// The local endpoint name, ie: server
+    endpoint: String,
+    /// The application on the other side of the relation
+    remote_app: String,
+    /// Data bags for the local unit and every remote unit, keyed by unit name
+    data: BTreeMap<String, BTreeMap<String, String>>,
+    /// Data bags for the local and remote application, keyed by application name
+    app_data: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A secret the unit under test owns or has been granted
+#[derive(Clone, Debug, Default)]
+struct FakeSecret {
+    /// Whether the unit under test created the secret with secret-add
+    owned: bool,
+    /// application or unit
+    owner: String,
+    label: Option<String>,
+    description: Option<String>,
+    expire: Option<String>,
+    rotate: Option<String>,
+    /// The content of every revision that has not been removed
+    revisions: BTreeMap<u32, BTreeMap<String, String>>,
+    /// The revision secret-get returns to a consumer until it refreshes
+    tracked: u32,
+    /// The relation ids and units the secret has been granted to
+    grants: BTreeSet<String>,
+}
+
+impl FakeSecret {
+    fn latest(&self) -> u32 {
+        self.revisions.keys().next_back().cloned().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModelState {
+    unit: String,
+    leader: bool,
+    leader_settings: BTreeMap<String, String>,
+    private_address: String,
+    public_address: String,
+    networks: BTreeMap<String, NetworkInfo>,
+    config: BTreeMap<String, Value>,
+    relations: BTreeMap<String, FakeRelation>,
+    next_relation_id: usize,
+    /// Open ports and the endpoints they are open for, `*` meaning every endpoint
+    ports: BTreeMap<String, BTreeSet<String>>,
+    status: Option<(String, String)>,
+    application_status: Option<(String, String)>,
+    storage: BTreeMap<String, String>,
+    secrets: BTreeMap<String, FakeSecret>,
+    next_secret_id: usize,
+    action_params: serde_json::Map<String, Value>,
+    action_results: BTreeMap<String, String>,
+    action_failure: Option<String>,
+    action_logs: Vec<String>,
+    env: HashMap<String, String>,
+    logs: Vec<(Option<String>, String)>,
+    calls: Vec<(String, Vec<String>)>,
+}
+
+/// A fake Juju model seen from the point of view of a single unit.
+/// Clones share the same state so a test can keep a handle while the model is installed as
+/// the hook tool runner.
+#[derive(Clone, Debug)]
+pub struct FakeModel {
+    state: Rc<RefCell<ModelState>>,
+}
+
+impl FakeModel {
+    /// Create a model for `unit`, ie: gluster/0
+    pub fn new(unit: &str) -> FakeModel {
+        let mut state = ModelState {
+            unit: unit.to_string(),
+            private_address: "10.0.0.10".to_string(),
+            public_address: "10.0.0.10".to_string(),
+            ..Default::default()
+        };
+        state.env.insert("JUJU_UNIT_NAME".to_string(), unit.to_string());
+        FakeModel { state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Install this model as the hook tool runner for the current thread so that calls to the
+    /// library outside of `run_hook` are also answered by it.  The runner that was installed
+    /// before is put back when the returned guard is dropped.
+    pub fn install(&self) -> RunnerGuard {
+        runner::install_runner(self.clone())
+    }
+
+    /// Set a charm config option.  Strings, booleans and numbers are all accepted so options
+    /// keep the types they are declared with in config.yaml
+    pub fn set_config<V: Into<Value>>(&self, key: &str, value: V) {
+        self.state.borrow_mut().config.insert(key.to_string(), value.into());
+    }
+
+    /// Decide whether the unit under test is the leader
+    pub fn set_leader(&self, leader: bool) {
+        self.state.borrow_mut().leader = leader;
+    }
+
+    /// Set a leadership setting as if the leader had called leader-set
+    pub fn set_leader_data(&self, key: &str, value: &str) {
+        set_or_remove(&mut self.state.borrow_mut().leader_settings, key, value);
+    }
+
+    /// The leadership settings shared with every unit
+    pub fn leader_data(&self) -> HashMap<String, String> {
+        let state = self.state.borrow();
+        state.leader_settings.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// Set the addresses unit-get reports for the unit under test
+    pub fn set_addresses(&self, private_address: &str, public_address: &str) {
+        let mut state = self.state.borrow_mut();
+        state.private_address = private_address.to_string();
+        state.public_address = public_address.to_string();
+    }
+
+    /// Set what network-get reports for `endpoint`.  Endpoints without network information
+    /// are reported as bound to the unit's private address.
+    pub fn set_network_info(&self, endpoint: &str, info: NetworkInfo) {
+        self.state.borrow_mut().networks.insert(endpoint.to_string(), info);
+    }
+
+    /// Set a variable in the hook environment, ie: JUJU_ACTION_NAME
+    pub fn set_env(&self, key: &str, value: &str) {
+        self.state.borrow_mut().env.insert(key.to_string(), value.to_string());
+    }
+
+    /// Relate the local `endpoint` to `remote_app`.  Returns the new relation id, ie: server:0
+    pub fn add_relation(&self, endpoint: &str, remote_app: &str) -> String {
+        let mut state = self.state.borrow_mut();
+        let id = format!("{}:{}", endpoint, state.next_relation_id);
+        state.next_relation_id += 1;
+        let unit = state.unit.clone();
+        let mut relation = FakeRelation {
+            endpoint: endpoint.to_string(),
+            remote_app: remote_app.to_string(),
+            data: BTreeMap::new(),
+            app_data: BTreeMap::new(),
+        };
+        relation.app_data.insert(application(&unit).to_string(), BTreeMap::new());
+        relation.app_data.insert(remote_app.to_string(), BTreeMap::new());
+        relation.data.insert(unit, BTreeMap::new());
+        state.relations.insert(id.clone(), relation);
+        id
+    }
+
+    /// Add a remote unit to an existing relation
+    /// # Panics
+    /// Panics if the relation does not exist
+    pub fn add_relation_unit(&self, relation_id: &str, unit: &str) {
+        let mut state = self.state.borrow_mut();
+        let relation = state.relations.get_mut(relation_id).expect("unknown relation id");
+        relation.data.entry(unit.to_string()).or_default();
+    }
+
+    /// Update the data bag `unit` has published on a relation.  Empty values remove the key.
+    /// # Panics
+    /// Panics if the relation does not exist
+    pub fn update_relation_data(&self, relation_id: &str, unit: &str, data: &[(&str, &str)]) {
+        let mut state = self.state.borrow_mut();
+        let relation = state.relations.get_mut(relation_id).expect("unknown relation id");
+        let bag = relation.data.entry(unit.to_string()).or_default();
+        for &(key, value) in data {
+            set_or_remove(bag, key, value);
+        }
+    }
+
+    /// Update the application data bag `app` has published on a relation.  Empty values remove
+    /// the key.
+    /// # Panics
+    /// Panics if the relation does not exist
+    pub fn update_app_relation_data(&self, relation_id: &str, app: &str, data: &[(&str, &str)]) {
+        let mut state = self.state.borrow_mut();
+        let relation = state.relations.get_mut(relation_id).expect("unknown relation id");
+        let bag = relation.app_data.entry(app.to_string()).or_default();
+        for &(key, value) in data {
+            set_or_remove(bag, key, value);
+        }
+    }
+
+    /// Attach storage to the unit.  Returns the storage id, ie: brick/0
+    pub fn add_storage(&self, name: &str, location: &str) -> String {
+        let mut state = self.state.borrow_mut();
+        let prefix = format!("{}/", name);
+        let index = state.storage.keys().filter(|id| id.starts_with(&prefix)).count();
+        let id = format!("{}/{}", name, index);
+        state.storage.insert(id.clone(), location.to_string());
+        id
+    }
+
+    /// Share a secret owned by another application with the unit under test.  Returns its URI,
+    /// ie: secret:fake0000000000000000
+    pub fn add_secret(&self, content: &[(&str, &str)]) -> String {
+        let secret = FakeSecret {
+            owner: "application".to_string(),
+            ..Default::default()
+        };
+        let content = content.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        let id = self.state.borrow_mut().new_secret(secret, content);
+        format!("secret:{}", id)
+    }
+
+    /// Add a revision to a secret as its owner would with secret-set.  Consumers keep seeing
+    /// the old revision until they refresh.
+    /// # Panics
+    /// Panics if the secret does not exist
+    pub fn update_secret(&self, uri: &str, content: &[(&str, &str)]) {
+        let mut state = self.state.borrow_mut();
+        let secret = state.secrets.get_mut(&secret_id(uri)).expect("unknown secret");
+        let revision = secret.latest() + 1;
+        let content = content.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        secret.revisions.insert(revision, content);
+    }
+
+    /// The content of the latest revision of a secret, or an empty map if there is none
+    pub fn secret_content(&self, uri: &str) -> HashMap<String, String> {
+        let state = self.state.borrow();
+        match state.secrets.get(&secret_id(uri)).and_then(|s| s.revisions.values().next_back()) {
+            Some(content) => content.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => HashMap::new(),
+        }
+    }
+
+    /// The relation ids and unit names a secret has been granted to
+    pub fn secret_grants(&self, uri: &str) -> Vec<String> {
+        let state = self.state.borrow();
+        match state.secrets.get(&secret_id(uri)) {
+            Some(secret) => secret.grants.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Set the parameters the running action was called with
+    pub fn set_action_params(&self, params: &[(&str, &str)]) {
+        for &(key, value) in params {
+            self.set_action_param(key, value);
+        }
+    }
+
+    /// Set a single, typed action parameter.  Dotted keys create nested parameters,
+    /// ie: `target.path`
+    pub fn set_action_param<V: Into<Value>>(&self, key: &str, value: V) {
+        let mut state = self.state.borrow_mut();
+        let mut params = &mut state.action_params;
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().unwrap_or_default();
+        for part in parts {
+            let entry = params.entry(part.to_string())
+                .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(serde_json::Map::new());
+            }
+            params = entry.as_object_mut().expect("entry was just made a map");
+        }
+        params.insert(last.to_string(), value.into());
+    }
+
+    /// Run `hook_name` against `registry` with this model installed as the runner
+    pub fn run_hook<E, R>(&self, hook_name: &str, registry: R) -> Result<(), String>
+        where E: fmt::Display,
+              R: Into<HookRegistry<E>>
+    {
+        self.set_env("JUJU_HOOK_NAME", hook_name);
+        let result = runner::with_runner(self.clone(), || ::process_hooks(registry));
+        self.state.borrow_mut().env.remove("JUJU_HOOK_NAME");
+        result
+    }
+
+    /// Run the action `action_name` against `registry` with this model installed as the runner
+    pub fn run_action(&self, action_name: &str, registry: Vec<Action>) -> Result<(), String> {
+        self.set_env("JUJU_ACTION_NAME", action_name);
+        let result = runner::with_runner(self.clone(), || ::process_actions(registry));
+        self.state.borrow_mut().env.remove("JUJU_ACTION_NAME");
+        result
+    }
+
+    /// Run a relation hook as if `remote_unit` triggered it on `relation_id`
+    pub fn run_relation_hook<E, R>(&self,
+                                   hook_name: &str,
+                                   relation_id: &str,
+                                   remote_unit: &str,
+                                   registry: R)
+                                   -> Result<(), String>
+        where E: fmt::Display,
+              R: Into<HookRegistry<E>>
+    {
+        let endpoint = match self.state.borrow().relations.get(relation_id) {
+            Some(relation) => relation.endpoint.clone(),
+            None => return Err(format!("Unknown relation id {}", relation_id)),
+        };
+        self.set_env("JUJU_RELATION", &endpoint);
+        self.set_env("JUJU_RELATION_ID", relation_id);
+        self.set_env("JUJU_REMOTE_UNIT", remote_unit);
+        self.set_env("JUJU_REMOTE_APP", application(remote_unit));
+        let result = self.run_hook(hook_name, registry);
+        let mut state = self.state.borrow_mut();
+        for key in &["JUJU_RELATION", "JUJU_RELATION_ID", "JUJU_REMOTE_UNIT", "JUJU_REMOTE_APP"] {
+            state.env.remove(*key);
+        }
+        result
+    }
+
+    /// Run a secret hook, ie: secret-changed, for the secret `uri`
+    pub fn run_secret_hook<E, R>(&self,
+                                 hook_name: &str,
+                                 uri: &str,
+                                 registry: R)
+                                 -> Result<(), String>
+        where E: fmt::Display,
+              R: Into<HookRegistry<E>>
+    {
+        let (label, revision) = match self.state.borrow().secrets.get(&secret_id(uri)) {
+            Some(secret) => (secret.label.clone(), secret.latest()),
+            None => return Err(format!("Unknown secret {}", uri)),
+        };
+        self.set_env("JUJU_SECRET_ID", uri);
+        self.set_env("JUJU_SECRET_REVISION", &revision.to_string());
+        if let Some(label) = label {
+            self.set_env("JUJU_SECRET_LABEL", &label);
+        }
+        let result = self.run_hook(hook_name, registry);
+        let mut state = self.state.borrow_mut();
+        for key in &["JUJU_SECRET_ID", "JUJU_SECRET_REVISION", "JUJU_SECRET_LABEL"] {
+            state.env.remove(*key);
+        }
+        result
+    }
+
+    /// The workload status and message last set by the charm
+    pub fn status(&self) -> Option<(String, String)> {
+        self.state.borrow().status.clone()
+    }
+
+    /// The application status and message last set by the leader
+    pub fn application_status(&self) -> Option<(String, String)> {
+        self.state.borrow().application_status.clone()
+    }
+
+    /// Ports opened by the charm, ie: 80/tcp
+    pub fn opened_ports(&self) -> Vec<String> {
+        self.state.borrow().ports.keys().cloned().collect()
+    }
+
+    /// The data bag `unit` has on a relation.  Pass the local unit to see what the charm set.
+    pub fn relation_data(&self, relation_id: &str, unit: &str) -> HashMap<String, String> {
+        let state = self.state.borrow();
+        match state.relations.get(relation_id).and_then(|r| r.data.get(unit)) {
+            Some(bag) => bag.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => HashMap::new(),
+        }
+    }
+
+    /// The application data bag `app` has on a relation.  Pass the local application to see what
+    /// the charm set.
+    pub fn app_relation_data(&self, relation_id: &str, app: &str) -> HashMap<String, String> {
+        let state = self.state.borrow();
+        match state.relations.get(relation_id).and_then(|r| r.app_data.get(app)) {
+            Some(bag) => bag.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => HashMap::new(),
+        }
+    }
+
+    /// Results recorded with action-set, keyed by the dotted keys the charm used
+    pub fn action_results(&self) -> HashMap<String, String> {
+        let state = self.state.borrow();
+        state.action_results.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// The message passed to action-fail, if the action failed
+    pub fn action_failure(&self) -> Option<String> {
+        self.state.borrow().action_failure.clone()
+    }
+
+    /// Progress messages sent with action-log
+    pub fn action_logs(&self) -> Vec<String> {
+        self.state.borrow().action_logs.clone()
+    }
+
+    /// Every message logged with juju-log along with its level
+    pub fn logs(&self) -> Vec<(Option<String>, String)> {
+        self.state.borrow().logs.clone()
+    }
+
+    /// Every hook tool run against the model along with its arguments, in the order they ran
+    pub fn calls(&self) -> Vec<(String, Vec<String>)> {
+        self.state.borrow().calls.clone()
+    }
+}
+
+impl HookToolRunner for FakeModel {
+    fn run(&self, tool: &str, args: &[String]) -> Result<ToolOutput, JujuError> {
+        let mut state = self.state.borrow_mut();
+        state.calls.push((tool.to_string(), args.to_vec()));
+        let args = Args::parse(args);
+        let result = match tool {
+            "config-get" => state.config_get(&args),
+            "is-leader" => Ok(format!("{}\n", if state.leader { "True" } else { "False" })),
+            "unit-get" => state.unit_get(&args),
+            "network-get" => state.network_get(&args),
+            "leader-get" => state.leader_get(&args),
+            "leader-set" => state.leader_set(&args),
+            "relation-get" => state.relation_get(&args),
+            "relation-set" => state.relation_set(&args),
+            "relation-list" => state.relation_list(&args),
+            "relation-ids" => state.relation_ids(&args),
+            "open-port" => state.port(&args, true),
+            "close-port" => state.port(&args, false),
+            "opened-ports" => state.opened_ports(&args),
+            "status-set" => state.status_set(&args),
+            "status-get" => state.status_get(&args),
+            "action-get" => state.action_get(&args),
+            "action-set" => state.action_set(&args),
+            "action-fail" => state.action_fail(&args),
+            "action-log" => state.action_log(&args),
+            "storage-get" => state.storage_get(&args),
+            "storage-list" => Ok(lines(state.storage.keys())),
+            "juju-log" => state.juju_log(&args),
+            "secret-add" => state.secret_add(&args),
+            "secret-get" => state.secret_get(&args),
+            "secret-set" => state.secret_set(&args),
+            "secret-remove" => state.secret_remove(&args),
+            "secret-grant" => state.secret_grant(&args, true),
+            "secret-revoke" => state.secret_grant(&args, false),
+            "secret-ids" => state.secret_ids(&args),
+            "secret-info-get" => state.secret_info_get(&args),
+            _ => return Err(JujuError::ToolNotFound { tool: tool.to_string() }),
+        };
+        match result {
+            Ok(stdout) => Ok(ToolOutput::success(&stdout)),
+            Err(stderr) => Ok(ToolOutput::failure(1, &format!("ERROR {}\n", stderr))),
+        }
+    }
+
+    fn env_var(&self, key: &str) -> Result<String, VarError> {
+        self.state.borrow().env.get(key).cloned().ok_or(VarError::NotPresent)
+    }
+}
+
+/// The flags the hook tools accept, pulled out from the positional arguments
+#[derive(Debug, Default)]
+struct Args {
+    relation: Option<String>,
+    storage: Option<String>,
+    level: Option<String>,
+    format: Option<String>,
+    file: Option<String>,
+    label: Option<String>,
+    description: Option<String>,
+    expire: Option<String>,
+    rotate: Option<String>,
+    owner: Option<String>,
+    revision: Option<String>,
+    unit: Option<String>,
+    /// Comma separated endpoints for open-port, or an empty string for `opened-ports --endpoints`
+    endpoints: Option<String>,
+    all: bool,
+    app: bool,
+    application: bool,
+    include_data: bool,
+    peek: bool,
+    refresh: bool,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn json(&self) -> bool {
+        self.format.as_ref().map(|f| f == "json").unwrap_or(false)
+    }
+
+    fn parse(args: &[String]) -> Args {
+        let mut parsed = Args::default();
+        let mut iter = args.iter().peekable();
+        while let Some(arg) = iter.next() {
+            match arg.as_ref() {
+                "-r" | "--relation" => parsed.relation = iter.next().cloned(),
+                "-s" => parsed.storage = iter.next().cloned(),
+                "-l" | "--log-level" => parsed.level = iter.next().cloned(),
+                "-a" | "--all" => parsed.all = true,
+                "--app" => parsed.app = true,
+                "--application" => parsed.application = true,
+                "--include-data" => parsed.include_data = true,
+                "--format" => parsed.format = iter.next().cloned(),
+                "--file" => parsed.file = iter.next().cloned(),
+                "--label" => parsed.label = iter.next().cloned(),
+                "--description" => parsed.description = iter.next().cloned(),
+                "--expire" => parsed.expire = iter.next().cloned(),
+                "--rotate" => parsed.rotate = iter.next().cloned(),
+                "--owner" => parsed.owner = iter.next().cloned(),
+                "--revision" => parsed.revision = iter.next().cloned(),
+                "--unit" => parsed.unit = iter.next().cloned(),
+                // A value for open-port and close-port, a flag for opened-ports
+                "--endpoints" => {
+                    parsed.endpoints = match iter.peek() {
+                        Some(value) if !value.starts_with('-') => iter.next().cloned(),
+                        _ => Some(String::new()),
+                    }
+                }
+                "--peek" => parsed.peek = true,
+                "--refresh" => parsed.refresh = true,
+                _ if arg.starts_with("--format=") => {
+                    parsed.format = Some(arg["--format=".len()..].to_string())
+                }
+                _ if arg.starts_with("--relation=") => {
+                    parsed.relation = Some(arg["--relation=".len()..].to_string())
+                }
+                // Juju treats anything else glued to -r as the value, leading space and all
+                _ if arg.starts_with("-r") => parsed.relation = Some(arg[2..].to_string()),
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+        parsed
+    }
+}
+
+impl ModelState {
+    fn config_get(&self, args: &Args) -> Result<String, String> {
+        match args.positional.first() {
+            Some(key) if !args.all => {
+                let value = self.config.get(key).cloned().unwrap_or(Value::Null);
+                if args.json() {
+                    Ok(format!("{}\n", value))
+                } else {
+                    Ok(plain_value(&value))
+                }
+            }
+            _ if args.json() => Ok(format!("{}\n", json_map(&self.config))),
+            _ => {
+                Ok(self.config.iter().map(|(k, v)| format!("{}: {}", k, plain_value(v))).collect())
+            }
+        }
+    }
+
+    fn network_get(&self, args: &Args) -> Result<String, String> {
+        if let Some(ref id) = args.relation {
+            if !self.relations.contains_key(id) {
+                return Err(format!("invalid value {:?} for option -r: relation not found", id));
+            }
+        }
+        let endpoint = match args.positional.first() {
+            Some(endpoint) => endpoint,
+            None => return Err("no endpoint specified".to_string()),
+        };
+        let info = match self.networks.get(endpoint) {
+            Some(info) => info.clone(),
+            None => {
+                NetworkInfo {
+                    bind_addresses: vec![BindAddress {
+                                             interface_name: "eth0".to_string(),
+                                             mac_address: "00:16:3e:00:00:01".to_string(),
+                                             addresses: vec![InterfaceAddress {
+                                                                 address: self.private_address
+                                                                     .clone(),
+                                                                 ..Default::default()
+                                                             }],
+                                         }],
+                    ingress_addresses: vec![self.private_address.clone()],
+                    egress_subnets: vec![format!("{}/32", self.private_address)],
+                }
+            }
+        };
+        serde_json::to_string(&info).map(|s| format!("{}\n", s)).map_err(|e| e.to_string())
+    }
+
+    fn leader_get(&self, args: &Args) -> Result<String, String> {
+        let value = match args.positional.first() {
+            Some(key) => self.leader_settings.get(key).cloned().map(Value::String),
+            None if self.leader_settings.is_empty() => None,
+            None => Some(json_map(&self.leader_settings)),
+        };
+        let value = value.unwrap_or(Value::Null);
+        if args.json() {
+            Ok(format!("{}\n", value))
+        } else if let Value::Object(ref map) = value {
+            Ok(map.iter().map(|(k, v)| format!("{}: {}", k, plain_value(v))).collect())
+        } else {
+            Ok(plain_value(&value))
+        }
+    }
+
+    fn leader_set(&mut self, args: &Args) -> Result<String, String> {
+        if !self.leader {
+            return Err("cannot write leadership settings: cannot write settings: not the \
+                        leader"
+                .to_string());
+        }
+        for setting in &args.positional {
+            let (key, value) = split_setting(setting)?;
+            set_or_remove(&mut self.leader_settings, key, value);
+        }
+        Ok(String::new())
+    }
+
+    fn unit_get(&self, args: &Args) -> Result<String, String> {
+        match args.positional.first().map(|s| s.as_ref()) {
+            Some("private-address") => Ok(format!("{}\n", self.private_address)),
+            Some("public-address") => Ok(format!("{}\n", self.public_address)),
+            other => Err(format!("unknown setting {:?}", other)),
+        }
+    }
+
+    /// Resolve the relation a tool call refers to, either from -r or the hook environment
+    fn relation_id(&self, args: &Args) -> Result<String, String> {
+        let id = match args.relation {
+            Some(ref id) => id.clone(),
+            None => {
+                match self.env.get("JUJU_RELATION_ID") {
+                    Some(id) => id.clone(),
+                    None => return Err("no relation id specified".to_string()),
+                }
+            }
+        };
+        if self.relations.contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(format!("invalid value {:?} for option -r: relation not found", id))
+        }
+    }
+
+    fn relation_get(&self, args: &Args) -> Result<String, String> {
+        let id = self.relation_id(args)?;
+        let key = match args.positional.first() {
+            Some(key) => key.clone(),
+            None => "-".to_string(),
+        };
+        let (default, bags) = if args.app {
+            ("JUJU_REMOTE_APP", &self.relations[&id].app_data)
+        } else {
+            ("JUJU_REMOTE_UNIT", &self.relations[&id].data)
+        };
+        let name = match args.positional.get(1) {
+            Some(name) => name.clone(),
+            None => {
+                match self.env.get(default) {
+                    Some(name) => name.clone(),
+                    None => return Err("no unit or application specified".to_string()),
+                }
+            }
+        };
+        let bag = match bags.get(&name) {
+            Some(bag) => bag,
+            None => return Err(format!("cannot read settings for {:?}", name)),
+        };
+        match (key.as_ref(), args.json()) {
+            ("-", true) => Ok(format!("{}\n", json_map(bag))),
+            ("-", false) => Ok(yaml_map(bag)),
+            (key, true) => {
+                Ok(format!("{}\n", bag.get(key).cloned().map(Value::String).unwrap_or(Value::Null)))
+            }
+            (key, false) => Ok(bag.get(key).map(|v| format!("{}\n", v)).unwrap_or_default()),
+        }
+    }
+
+    fn relation_set(&mut self, args: &Args) -> Result<String, String> {
+        let id = self.relation_id(args)?;
+        if args.app && !self.leader {
+            return Err("cannot write relation settings: application settings can only be \
+                        written by the leader"
+                .to_string());
+        }
+        let unit = self.unit.clone();
+        let relation = self.relations.get_mut(&id).unwrap();
+        let bag = if args.app {
+            relation.app_data.entry(application(&unit).to_string()).or_default()
+        } else {
+            relation.data.entry(unit).or_default()
+        };
+        for (key, value) in &settings(args, &args.positional)? {
+            set_or_remove(bag, key, value);
+        }
+        Ok(String::new())
+    }
+
+    fn relation_list(&self, args: &Args) -> Result<String, String> {
+        let id = self.relation_id(args)?;
+        let relation = &self.relations[&id];
+        let prefix = format!("{}/", relation.remote_app);
+        // On peer relations the local unit has a data bag too, but is never listed
+        Ok(lines(relation.data
+            .keys()
+            .filter(|unit| unit.starts_with(&prefix) && **unit != self.unit)))
+    }
+
+    fn relation_ids(&self, args: &Args) -> Result<String, String> {
+        let endpoint = match args.positional.first() {
+            Some(endpoint) => endpoint.clone(),
+            None => {
+                match self.env.get("JUJU_RELATION") {
+                    Some(endpoint) => endpoint.clone(),
+                    None => return Err("no endpoint name specified".to_string()),
+                }
+            }
+        };
+        Ok(lines(self.relations
+            .iter()
+            .filter(|&(_, relation)| relation.endpoint == endpoint)
+            .map(|(id, _)| id)))
+    }
+
+    fn port(&mut self, args: &Args, open: bool) -> Result<String, String> {
+        let port = match args.positional.first() {
+            Some(port) if port.contains('/') || port == "icmp" => port.clone(),
+            Some(port) => format!("{}/tcp", port),
+            None => return Err("no port specified".to_string()),
+        };
+        let endpoints: BTreeSet<String> = match args.endpoints {
+            Some(ref endpoints) if !endpoints.is_empty() => {
+                endpoints.split(',').map(String::from).collect()
+            }
+            _ => BTreeSet::new(),
+        };
+        if open {
+            let open_for = self.ports.entry(port).or_default();
+            if endpoints.is_empty() {
+                open_for.insert("*".to_string());
+            } else {
+                open_for.extend(endpoints);
+            }
+        } else if endpoints.is_empty() {
+            self.ports.remove(&port);
+        } else if let Some(open_for) = self.ports.get_mut(&port) {
+            open_for.retain(|endpoint| !endpoints.contains(endpoint));
+            if open_for.is_empty() {
+                self.ports.remove(&port);
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn opened_ports(&self, args: &Args) -> Result<String, String> {
+        let ports: Vec<String> = if args.endpoints.is_some() {
+            self.ports
+                .iter()
+                .flat_map(|(port, open_for)| {
+                    open_for.iter().map(move |endpoint| format!("{} ({})", port, endpoint))
+                })
+                .collect()
+        } else {
+            self.ports.keys().cloned().collect()
+        };
+        if args.json() {
+            serde_json::to_string(&ports).map(|s| format!("{}\n", s)).map_err(|e| e.to_string())
+        } else {
+            Ok(lines(ports.iter()))
+        }
+    }
+
+    fn status_set(&mut self, args: &Args) -> Result<String, String> {
+        let state = args.positional.first().cloned().unwrap_or_default();
+        match state.as_ref() {
+            "maintenance" | "blocked" | "waiting" | "active" => {}
+            _ => return Err(format!("invalid status {:?}", state)),
+        }
+        let message = args.positional.get(1).cloned().unwrap_or_default();
+        if !args.application {
+            self.status = Some((state, message));
+        } else if self.leader {
+            self.application_status = Some((state, message));
+        } else {
+            return Err("setting application status: this unit is not the leader".to_string());
+        }
+        Ok(String::new())
+    }
+
+    fn status_get(&self, args: &Args) -> Result<String, String> {
+        // Like Juju, only the status itself is printed unless the data is asked for
+        let status_value = |status: &Option<(String, String)>| {
+            let (state, message) = status.clone()
+                .unwrap_or_else(|| ("unknown".to_string(), String::new()));
+            if !args.include_data {
+                return Value::String(state);
+            }
+            let mut value = serde_json::Map::new();
+            value.insert("status".to_string(), Value::String(state));
+            value.insert("message".to_string(), Value::String(message));
+            value.insert("status-data".to_string(), Value::Object(serde_json::Map::new()));
+            Value::Object(value)
+        };
+        let value = if args.application {
+            if !self.leader {
+                return Err("finding application status: this unit is not the leader".to_string());
+            }
+            let mut application = match status_value(&self.application_status) {
+                Value::Object(map) => map,
+                state => {
+                    let mut map = serde_json::Map::new();
+                    map.insert("status".to_string(), state);
+                    map
+                }
+            };
+            let mut units = serde_json::Map::new();
+            units.insert(self.unit.clone(), status_value(&self.status));
+            application.insert("units".to_string(), Value::Object(units));
+            let mut value = serde_json::Map::new();
+            value.insert("application-status".to_string(), Value::Object(application));
+            Value::Object(value)
+        } else {
+            status_value(&self.status)
+        };
+        match value {
+            _ if args.json() => Ok(format!("{}\n", value)),
+            Value::String(state) => Ok(format!("{}\n", state)),
+            other => serde_yaml::to_string(&other).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn action_get(&self, args: &Args) -> Result<String, String> {
+        let value = match args.positional.first() {
+            Some(key) => self.action_param(key).unwrap_or(Value::Null),
+            None => Value::Object(self.action_params.clone()),
+        };
+        match value {
+            _ if args.json() => Ok(format!("{}\n", value)),
+            Value::Object(ref map) => {
+                Ok(map.iter().map(|(k, v)| format!("{}: {}", k, plain_value(v))).collect())
+            }
+            ref other => Ok(plain_value(other)),
+        }
+    }
+
+    /// Look up an action parameter by its dotted key
+    fn action_param(&self, key: &str) -> Option<Value> {
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop()?;
+        let mut params = &self.action_params;
+        for part in parts {
+            params = params.get(part)?.as_object()?;
+        }
+        params.get(last).cloned()
+    }
+
+    fn action_set(&mut self, args: &Args) -> Result<String, String> {
+        for setting in &args.positional {
+            let (key, value) = split_setting(setting)?;
+            if !::valid_action_key(key) {
+                return Err(format!("key {:?} must start and end with lowercase alphanumeric, and \
+                                    contain only lowercase alphanumeric, hyphens and periods",
+                                   key));
+            }
+            self.action_results.insert(key.to_string(), value.to_string());
+        }
+        Ok(String::new())
+    }
+
+    fn action_fail(&mut self, args: &Args) -> Result<String, String> {
+        let message = args.positional.first().cloned().unwrap_or_default();
+        self.action_failure = Some(message);
+        Ok(String::new())
+    }
+
+    fn action_log(&mut self, args: &Args) -> Result<String, String> {
+        if !self.env.contains_key("JUJU_ACTION_NAME") {
+            return Err("action-log is only available while running an action".to_string());
+        }
+        self.action_logs.push(args.positional.join(" "));
+        Ok(String::new())
+    }
+
+    fn storage_get(&self, args: &Args) -> Result<String, String> {
+        let id = match args.storage {
+            Some(ref id) => id.clone(),
+            None => {
+                match self.env.get("JUJU_STORAGE_ID") {
+                    Some(id) => id.clone(),
+                    None => return Err("no storage instance specified".to_string()),
+                }
+            }
+        };
+        let location = match self.storage.get(&id) {
+            Some(location) => location,
+            None => return Err(format!("storage instance {:?} not found", id)),
+        };
+        match args.positional.first().map(|s| s.as_ref()) {
+            Some("location") => Ok(format!("{}\n", location)),
+            Some("kind") => Ok("filesystem\n".to_string()),
+            other => Err(format!("unknown storage attribute {:?}", other)),
+        }
+    }
+
+    fn new_secret(&mut self, mut secret: FakeSecret, content: BTreeMap<String, String>) -> String {
+        let id = format!("fake{:016}", self.next_secret_id);
+        self.next_secret_id += 1;
+        secret.revisions.insert(1, content);
+        secret.tracked = 1;
+        self.secrets.insert(id.clone(), secret);
+        id
+    }
+
+    /// Find the secret a tool call refers to, by the URI in the first argument or by --label
+    fn secret_id(&self, args: &Args) -> Result<String, String> {
+        let id = match (args.positional.first(), &args.label) {
+            (Some(uri), _) => secret_id(uri),
+            (None, Some(label)) => {
+                match self.secrets.iter().find(|(_, s)| s.label.as_ref() == Some(label)) {
+                    Some((id, _)) => id.clone(),
+                    None => return Err(format!("secret with label {:?} not found", label)),
+                }
+            }
+            (None, None) => return Err("secret URI or label required".to_string()),
+        };
+        if self.secrets.contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(format!("secret {:?} not found", id))
+        }
+    }
+
+    /// Find a secret the unit under test owns and check it is allowed to change it
+    fn owned_secret(&mut self, args: &Args) -> Result<&mut FakeSecret, String> {
+        let id = self.secret_id(args)?;
+        let leader = self.leader;
+        let secret = self.secrets.get_mut(&id).unwrap();
+        if !secret.owned {
+            return Err(format!("secret {:?} is not owned by this unit", id));
+        }
+        if secret.owner == "application" && !leader {
+            return Err("application owned secrets can only be changed by the leader".to_string());
+        }
+        Ok(secret)
+    }
+
+    fn secret_add(&mut self, args: &Args) -> Result<String, String> {
+        let owner = args.owner.clone().unwrap_or_else(|| "application".to_string());
+        if owner == "application" && !self.leader {
+            return Err("cannot create an application owned secret: not the leader".to_string());
+        }
+        let content = settings(args, &args.positional)?;
+        if content.is_empty() {
+            return Err("missing secret value".to_string());
+        }
+        let secret = FakeSecret {
+            owned: true,
+            owner,
+            label: args.label.clone(),
+            description: args.description.clone(),
+            expire: args.expire.clone(),
+            rotate: args.rotate.clone(),
+            ..Default::default()
+        };
+        let id = self.new_secret(secret, content);
+        Ok(format!("secret:{}\n", id))
+    }
+
+    fn secret_get(&mut self, args: &Args) -> Result<String, String> {
+        let id = self.secret_id(args)?;
+        let secret = self.secrets.get_mut(&id).unwrap();
+        // Owners always see the latest revision
+        let revision = if secret.owned || args.peek || args.refresh {
+            secret.latest()
+        } else {
+            secret.tracked
+        };
+        if args.refresh {
+            secret.tracked = revision;
+        }
+        let content = match secret.revisions.get(&revision) {
+            Some(content) => content,
+            None => return Err(format!("secret {:?} revision {} not found", id, revision)),
+        };
+        match (args.positional.get(1), args.json()) {
+            (None, true) => Ok(format!("{}\n", json_map(content))),
+            (None, false) => Ok(yaml_map(content)),
+            (Some(key), true) => {
+                let value = content.get(key).cloned().map(Value::String).unwrap_or(Value::Null);
+                Ok(format!("{}\n", value))
+            }
+            (Some(key), false) => {
+                Ok(content.get(key).map(|v| format!("{}\n", v)).unwrap_or_default())
+            }
+        }
+    }
+
+    fn secret_set(&mut self, args: &Args) -> Result<String, String> {
+        let content = settings(args, args.positional.get(1..).unwrap_or_default())?;
+        let secret = self.owned_secret(args)?;
+        if !content.is_empty() {
+            let revision = secret.latest() + 1;
+            secret.revisions.insert(revision, content);
+        }
+        let fields = [(&mut secret.label, &args.label),
+                      (&mut secret.description, &args.description),
+                      (&mut secret.expire, &args.expire),
+                      (&mut secret.rotate, &args.rotate)];
+        for (field, value) in fields {
+            if value.is_some() {
+                *field = value.clone();
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn secret_remove(&mut self, args: &Args) -> Result<String, String> {
+        let id = self.secret_id(args)?;
+        let secret = self.owned_secret(args)?;
+        match args.revision {
+            Some(ref revision) => {
+                let revision: u32 =
+                    revision.parse().map_err(|_| format!("invalid revision {:?}", revision))?;
+                if secret.revisions.remove(&revision).is_none() {
+                    return Err(format!("secret {:?} revision {} not found", id, revision));
+                }
+            }
+            None => {
+                self.secrets.remove(&id);
+            }
+        }
+        Ok(String::new())
+    }
+
+    fn secret_grant(&mut self, args: &Args, grant: bool) -> Result<String, String> {
+        let relation = match args.relation {
+            Some(_) => self.relation_id(args)?,
+            None => return Err("relation id required".to_string()),
+        };
+        let grantee = args.unit.clone().unwrap_or(relation);
+        let secret = self.owned_secret(args)?;
+        if grant {
+            secret.grants.insert(grantee);
+        } else {
+            secret.grants.remove(&grantee);
+        }
+        Ok(String::new())
+    }
+
+    fn secret_ids(&self, args: &Args) -> Result<String, String> {
+        let ids: Vec<String> = self.secrets
+            .iter()
+            .filter(|&(_, secret)| secret.owned)
+            .map(|(id, _)| format!("secret:{}", id))
+            .collect();
+        if args.json() {
+            serde_json::to_string(&ids).map(|s| format!("{}\n", s)).map_err(|e| e.to_string())
+        } else {
+            Ok(lines(ids.iter()))
+        }
+    }
+
+    fn secret_info_get(&self, args: &Args) -> Result<String, String> {
+        let id = self.secret_id(args)?;
+        let secret = &self.secrets[&id];
+        if !secret.owned {
+            return Err(format!("secret {:?} is not owned by this unit", id));
+        }
+        let mut info = serde_json::Map::new();
+        info.insert("revision".to_string(), Value::from(secret.latest()));
+        info.insert("owner".to_string(), Value::from(secret.owner.clone()));
+        let optional = [("label", &secret.label),
+                        ("description", &secret.description),
+                        ("expiry", &secret.expire),
+                        ("rotation", &secret.rotate)];
+        for (key, value) in optional {
+            if let Some(ref value) = *value {
+                info.insert(key.to_string(), Value::from(value.clone()));
+            }
+        }
+        let mut output = serde_json::Map::new();
+        output.insert(id, Value::Object(info));
+        Ok(format!("{}\n", Value::Object(output)))
+    }
+
+    fn juju_log(&mut self, args: &Args) -> Result<String, String> {
+        if let Some(ref level) = args.level {
+            if level.parse::<LogLevel>().is_err() {
+                return Err(format!("invalid log level {:?}", level));
+            }
+        }
+        self.logs.push((args.level.clone(), args.positional.join(" ")));
+        Ok(String::new())
+    }
+}
+
+/// The application a unit belongs to, ie: gluster for gluster/0
+fn application(unit: &str) -> &str {
+    unit.split('/').next().unwrap_or(unit)
+}
+
+/// The id part of a secret URI, ie: fake0000000000000000 for secret:fake0000000000000000
+fn secret_id(uri: &str) -> String {
+    uri.parse::<SecretUri>().map(|uri| uri.id().to_string()).unwrap_or_else(|_| uri.to_string())
+}
+
+/// Settings from the YAML file passed with --file and `key=value` arguments.  Like Juju, the
+/// ones on the command line win over the ones in the file.
+fn settings(args: &Args, positional: &[String]) -> Result<BTreeMap<String, String>, String> {
+    let mut settings: BTreeMap<String, String> = match args.file {
+        Some(ref path) => {
+            let yaml = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?
+        }
+        None => BTreeMap::new(),
+    };
+    for setting in positional {
+        let (key, value) = split_setting(setting)?;
+        settings.insert(key.to_string(), value.to_string());
+    }
+    Ok(settings)
+}
+
+fn split_setting(setting: &str) -> Result<(&str, &str), String> {
+    match setting.find('=') {
+        Some(index) => Ok((&setting[..index], &setting[index + 1..])),
+        None => Err(format!("expected \"key=value\", got {:?}", setting)),
+    }
+}
+
+fn set_or_remove(bag: &mut BTreeMap<String, String>, key: &str, value: &str) {
+    if value.is_empty() {
+        bag.remove(key);
+    } else {
+        bag.insert(key.to_string(), value.to_string());
+    }
+}
+
+/// Juju prints strings without quotes and nothing at all for unset values
+fn plain_value(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => format!("{}\n", s),
+        ref other => format!("{}\n", other),
+    }
+}
+
+fn json_map<V: Clone + Into<Value>>(map: &BTreeMap<String, V>) -> Value {
+    let object: serde_json::Map<String, Value> =
+        map.iter().map(|(k, v)| (k.clone(), v.clone().into())).collect();
+    Value::Object(object)
+}
+
+fn yaml_map(map: &BTreeMap<String, String>) -> String {
+    map.iter().map(|(k, v)| format!("{}: {}\n", k, v)).collect()
+}
+
+fn lines<I, S>(items: I) -> String
+    where I: Iterator<Item = S>,
+          S: AsRef<str>
+{
+    items.map(|item| format!("{}\n", item.as_ref())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FakeModel;
+    use Action;
+
+    fn relation_changed() -> Result<(), String> {
+        let hostname = ::relation_get("hostname").map_err(|e| e.to_string())?;
+        ::relation_set("peer", hostname.trim()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn brick_attached() -> Result<(), String> {
+        let location = ::storage_get("brick/0").map_err(|e| e.to_string())?;
+        status_set!(Maintenance format!("formatting {}", location.trim()));
+        Ok(())
+    }
+
+    #[derive(Deserialize)]
+    struct BackupParams {
+        #[serde(default)]
+        target: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct BackupResults {
+        saved_to: String,
+    }
+
+    fn backup(params: BackupParams) -> Result<BackupResults, String> {
+        assert_eq!(::action_name().unwrap(), "backup");
+        if params.target.is_empty() {
+            return Err("no target given".to_string());
+        }
+        Ok(BackupResults { saved_to: params.target })
+    }
+
+    #[test]
+    fn it_answers_relation_hooks_from_its_state() {
+        let model = FakeModel::new("gluster/0");
+        let id = model.add_relation("server", "gluster");
+        model.add_relation_unit(&id, "gluster/1");
+        model.update_relation_data(&id, "gluster/1", &[("hostname", "10.0.0.11")]);
+
+        model.run_relation_hook("server-relation-changed",
+                               &id,
+                               "gluster/1",
+                               vec![hook!("server-relation-changed", relation_changed)])
+            .unwrap();
+
+        let local = model.relation_data(&id, "gluster/0");
+        assert_eq!(local.get("peer"), Some(&"10.0.0.11".to_string()));
+    }
+
+    #[test]
+    fn it_answers_storage_and_status() {
+        let model = FakeModel::new("gluster/0");
+        model.add_storage("brick", "/srv/brick0");
+
+        model.run_hook("brick-storage-attached",
+                      vec![hook!("brick-storage-attached", brick_attached)])
+            .unwrap();
+
+        assert_eq!(model.status(),
+                   Some(("maintenance".to_string(), "formatting /srv/brick0".to_string())));
+        let _runner = model.install();
+        assert!(::runner::var("JUJU_HOOK_NAME").is_err());
+    }
+
+    #[test]
+    fn it_records_action_results_and_failures() {
+        let model = FakeModel::new("gluster/0");
+        model.set_action_params(&[("target", "/backups")]);
+        model.run_action("backup", vec![Action::new("backup", backup)]).unwrap();
+        assert_eq!(model.action_results().get("saved-to"),
+                   Some(&"/backups".to_string()));
+        assert!(::action_name().is_err());
+
+        let model = FakeModel::new("gluster/0");
+        assert_eq!(model.run_action("backup", vec![Action::new("backup", backup)]),
+                   Err("no target given".to_string()));
+        assert_eq!(model.action_failure(), Some("no target given".to_string()));
+    }
+
+    #[test]
+    fn it_tracks_leadership_and_ports_when_installed() {
+        let model = FakeModel::new("gluster/0");
+        let runner = model.install();
+        model.set_leader(true);
+        assert!(::is_leader().unwrap());
+
+        ::open_port(24007, ::Transport::Tcp).unwrap();
+        ::open_port(111, ::Transport::Udp).unwrap();
+        ::close_port(24007, ::Transport::Tcp).unwrap();
+        drop(runner);
+        assert!(::is_leader().is_err());
+
+        assert_eq!(model.opened_ports(), vec!["111/udp".to_string()]);
+    }
+}